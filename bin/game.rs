@@ -4,10 +4,12 @@ use hecs::*;
 use macroquad::prelude::*;
 use mecslib::{
     components::*,
-    system::{stateless::*, stateful::*},
+    system::{stateless::*, stateful::*, schedule::*, camera::*},
     data::{
         loading::TextureEnum,
         world::{State, UserState, run},
+        input::InputRecording,
+        actions::{Action, Binding},
     },
     create_texture_enum,
     sys,
@@ -46,48 +48,107 @@ impl Default for MyState {
 }
 
 impl UserState<Textures> for MyState {
-    fn initialize(s: &mut State<Self, Textures>) {
-        let grid_size = 1000;
-        s.usr.rand_map = RandomMapGen::new(grid_size, 40000, s.usr.rng.u64(0..u64::MAX));
-        // s.usr.voronoi_tiling.desired_points = 210;
-        let density = grid_size as f32 * 0.038;
-        let intensity = density / 2.0;
-        s.usr.voronoi_tiling.with_grid_points(grid_size, density as _, intensity);
-
-        // example of parenting:
-        // let transform = Transform::from_scale_angle_position(1.0, 0.0, (0.0, 0.0));
-        // let draw = Drawable::texture(s, Textures::test);
-        // let unit = s.world.spawn((transform, draw, Layer1));
-
-        // let transform = Transform::from_scale_angle_position(1.0, std::f32::consts::FRAC_PI_4, (10.0, 0.0));
-        // let draw = Drawable::texture(s, Textures::other);
-        // let parent = Parent {
-        //     parent: unit,
-        //     local_transform: transform,
-        // };
-        // let _other = s.world.spawn((Transform::default(), draw, parent, Layer2));
+    fn initialize(s: &mut GameState) {
+        s.actions.bind(PAUSE, Binding::Key(KeyCode::Space));
+        s.actions.bind(STEP, Binding::Key(KeyCode::Period));
+        s.actions.bind(SLOW_DOWN, Binding::Key(KeyCode::Minus));
+        s.actions.bind(SPEED_UP, Binding::Key(KeyCode::Equal));
+        s.actions.bind(SAVE_RECORDING, Binding::Key(KeyCode::F9));
+        // the default bindings can be overridden by a config file
+        match s.actions.load_from_file(CONTROLS_PATH) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                warn!("failed to load controls from {}: {}", CONTROLS_PATH, e);
+            }
+            _ => {}
+        }
+        match s.coords.load_settings_from_file(CAMERA_SETTINGS_PATH) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                warn!("failed to load camera settings from {}: {}", CAMERA_SETTINGS_PATH, e);
+            }
+            _ => {}
+        }
     }
 }
 
+fn setup_map(s: &mut GameState, _dt: f32) {
+    // seeded from the state so that replaying a recording generates the same map
+    s.usr.rng = fastrand::Rng::with_seed(s.seed);
+    s.insert_resource(ScreenShake::new(s.seed));
+    let grid_size = 1000;
+    s.usr.rand_map = RandomMapGen::new(grid_size, 40000, s.usr.rng.u64(0..u64::MAX));
+    // s.usr.voronoi_tiling.desired_points = 210;
+    let density = grid_size as f32 * 0.038;
+    let intensity = density / 2.0;
+    s.usr.voronoi_tiling.with_grid_points(grid_size, density as _, intensity);
+
+    // the map is drawn in a square in the middle of the screen, see: fill_generated_map.
+    // keep the camera around it, with some margin to see the edges of the map
+    let final_size = s.input.screen_size.y * 0.9;
+    let map_rect = Rect::new((s.input.screen_size.x - final_size) / 2.0, s.input.screen_size.y * 0.05, final_size, final_size);
+    let margin = final_size * 0.25;
+    s.coords.bounds = Some(Rect::new(map_rect.x - margin, map_rect.y - margin, map_rect.w + margin * 2.0, map_rect.h + margin * 2.0));
+
+    // minimap of the terrain and the tiles in the top right corner
+    let minimap_size = 200.0;
+    let viewport = Rect::new(s.input.screen_size.x - minimap_size - 10.0, 10.0, minimap_size, minimap_size);
+    let mut minimap = CameraView::new(viewport, LayerMask::NONE.with(0).with(6)).with_clear_color(BLACK);
+    minimap.coords.fit_rect(map_rect, 0.0);
+    s.world.spawn((minimap,));
+
+    // example of parenting:
+    // let transform = Transform::from_scale_angle_position(1.0, 0.0, (0.0, 0.0));
+    // let draw = Drawable::texture(s, Textures::test);
+    // let unit = s.world.spawn((transform, draw, Layer1));
+
+    // let transform = Transform::from_scale_angle_position(1.0, std::f32::consts::FRAC_PI_4, (10.0, 0.0));
+    // let draw = Drawable::texture(s, Textures::other);
+    // let parent = Parent {
+    //     parent: unit,
+    //     local_transform: transform,
+    // };
+    // let _other = s.world.spawn((Transform::default(), draw, parent, Layer2));
+}
+
 pub type MySystem = System<MyState, Textures>;
 pub type GameState = State<MyState, Textures>;
 
 create_texture_enum!(Textures; other, test, empty);
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Phase {
+    GeneratingTerrain,
+    TilingRegions,
+    Playing,
+}
+
 pub struct IsTile;
 const WATER_COLOR: Color = BLUE;
 const NON_HOVERED_TILE_COLOR: Color = Color::new(0.7, 0.7, 0.7, 1.0);
 const HOVERED_TILE_COLOR: Color = WHITE;
-
-fn get_all_systems() -> &'static [MySystem] {
-    &[
-        sys!(handle_pan),
-        sys!(update_children_transforms),
-        sys!(generate_tiles_voronoi),
-        sys!(draw_hovered_tiles),
-        sys!(fill_generated_map),
-        sys!(draw),
-    ]
+const RECORDING_PATH: &str = "recording.input";
+const CONTROLS_PATH: &str = "controls.cfg";
+const CAMERA_SETTINGS_PATH: &str = "camera.cfg";
+
+const PAUSE: Action = Action::Custom("Pause");
+const STEP: Action = Action::Custom("Step");
+const SLOW_DOWN: Action = Action::Custom("SlowDown");
+const SPEED_UP: Action = Action::Custom("SpeedUp");
+const SAVE_RECORDING: Action = Action::Custom("SaveRecording");
+
+fn get_schedule() -> Schedule<MyState, Textures> {
+    Schedule::new()
+        .with_system(Stage::Startup, sys!(setup_map))
+        .with_system(Stage::Update, sys!(draw_hovered_tiles))
+        .with_app_state(Phase::GeneratingTerrain)
+        .on_update(Phase::GeneratingTerrain, sys!(fill_generated_map))
+        .on_update(Phase::TilingRegions, sys!(generate_tiles_voronoi))
+        .with_system(Stage::PostUpdate, sys!(update_children_transforms))
+        .with_system(Stage::Render, sys!(handle_pan))
+        .with_system(Stage::Render, sys!(update_screen_shake))
+        .with_system(Stage::Render, sys!(draw))
+        // in the render stage so that it still runs while paused
+        .with_system(Stage::Render, sys!(handle_time_controls))
+        .with_system(Stage::Render, sys!(handle_input_recording))
 }
 
 pub fn create_rivers(s: &mut GameState) {
@@ -184,6 +245,38 @@ pub fn create_rivers(s: &mut GameState) {
     }
 }
 
+/// pause: pause/resume, step: step a single frame while paused,
+/// slow down/speed up: halve/double the simulation speed (0.25x - 4x)
+fn handle_time_controls(s: &mut GameState, _dt: f32) {
+    if s.action_pressed(PAUSE) {
+        s.time.toggle_pause();
+    }
+    if s.action_pressed(STEP) {
+        s.time.step();
+    }
+    if s.action_pressed(SLOW_DOWN) {
+        s.time.scale = (s.time.scale / 2.0).max(0.25);
+    }
+    if s.action_pressed(SPEED_UP) {
+        s.time.scale = (s.time.scale * 2.0).min(4.0);
+    }
+}
+
+/// save recording (F9 by default): stops recording the input and saves it to RECORDING_PATH.
+/// start the game with `--record` to record from the first frame,
+/// and with `--replay <path>` to replay a saved recording.
+fn handle_input_recording(s: &mut GameState, _dt: f32) {
+    if !s.action_pressed(SAVE_RECORDING) {
+        return;
+    }
+    if let Some(recording) = s.stop_recording() {
+        match recording.save_to_file(RECORDING_PATH) {
+            Ok(_) => warn!("saved input recording to {}", RECORDING_PATH),
+            Err(e) => warn!("failed to save input recording to {}: {}", RECORDING_PATH, e),
+        }
+    }
+}
+
 fn draw_hovered_tiles(s: &mut GameState, _dt: f32) {
     // for each invocation make sure we hide tiles that are not hovered:
     let mut cb = CommandBuffer::new();
@@ -193,7 +286,7 @@ fn draw_hovered_tiles(s: &mut GameState, _dt: f32) {
     cb.run_on(&mut s.world);
 
     // then unhide the tiles we hover:
-    let (mx, my) = mouse_position();
+    let (mx, my) = s.input.mouse_position();
     let (wx, wy) = s.coords.to_world(mx, my);
     let i32_coord = (wx as i32, wy as i32);
     let entity = match s.usr.tile_positions.get(&i32_coord) {
@@ -205,9 +298,6 @@ fn draw_hovered_tiles(s: &mut GameState, _dt: f32) {
 
 fn generate_tiles_voronoi(s: &mut GameState, _dt: f32) {
     let tiling = &mut s.usr.voronoi_tiling;
-    if !tiling.ready_to_tile || tiling.done {
-        return;
-    }
     let mut growths = tiling.next_n(&mut s.usr.rng, 10);
     if s.usr.voronoi_colors.is_empty() {
         for _ in 0..tiling.desired_points {
@@ -231,12 +321,12 @@ fn generate_tiles_voronoi(s: &mut GameState, _dt: f32) {
         cb.run_on(&mut s.world);
 
         // replace them with finalized, generated textures
-        let final_size = screen_height() * 0.9;
+        let final_size = s.input.screen_size.y * 0.9;
         let tile_size = final_size / s.usr.rand_map.square_size as f32;
         let center = Vec2::new(final_size / 2.0, final_size / 2.0);
-        let screen_center = Vec2::new(screen_width() / 2.0, screen_height() / 2.0);
+        let screen_center = s.input.screen_size / 2.0;
         let delta = screen_center - center;
-        for (_i, set) in tiling.growth_sets.drain(..).enumerate() {
+        for set in tiling.growth_sets.drain(..) {
             // let color = s.usr.voronoi_colors[i];
             let (transform, _drawable_solid, drawable_outline) = generate_texture_from_tileset(&set, tile_size, delta);
             let entity = s.world.spawn((transform, Layer6, drawable_outline, IsTile, Tint { d: NON_HOVERED_TILE_COLOR }));
@@ -251,6 +341,8 @@ fn generate_tiles_voronoi(s: &mut GameState, _dt: f32) {
             tiling.continue_with_open_set();
         } else {
             s.clear_color = WATER_COLOR;
+            s.set_app_state(Phase::Playing);
+            s.resource_mut::<ScreenShake>().add_trauma(0.6);
         }
     }
 }
@@ -360,8 +452,8 @@ fn generate_texture_from_tileset(
     let scaled_dist = scaled_corner.distance(scaled_origin);
     // macroquad::logging::warn!("Scaled dist {}, original dist {}. tile size {}", scaled_dist, original_dist, tile_size);
     let scale = scaled_dist / original_dist;
-    let outline_bytes = generate_texture_bytes_outline(&set, min_x, max_x, min_y, max_y);
-    let solid_bytes = generate_texture_bytes_solid(&set, min_x, max_x, min_y, max_y);
+    let outline_bytes = generate_texture_bytes_outline(set, min_x, max_x, min_y, max_y);
+    let solid_bytes = generate_texture_bytes_solid(set, min_x, max_x, min_y, max_y);
     let start_pt = Vec2::new(min_x as f32, min_y as f32);
     let pt = start_pt * tile_size;
     let width = width as u16;
@@ -384,11 +476,11 @@ fn color_tiles(
 ) {
     let d = s.textures[&Textures::empty];
     let d_size = d.width();
-    let final_size = screen_height() * 0.9;
+    let final_size = s.input.screen_size.y * 0.9;
     let tile_size = final_size / s.usr.rand_map.square_size as f32;
     let scale = tile_size / d_size;
     let center = Vec2::new(final_size / 2.0, final_size / 2.0);
-    let screen_center = Vec2::new(screen_width() / 2.0, screen_height() / 2.0);
+    let screen_center = s.input.screen_size / 2.0;
     let delta = screen_center - center;
 
     for (x, y) in tiles {
@@ -405,11 +497,11 @@ fn color_tiles(
 
 fn fill_generated_map(s: &mut GameState, _dt: f32) {
     let next = s.usr.rand_map.get_next();
-    let final_size = screen_height() * 0.9;
+    let final_size = s.input.screen_size.y * 0.9;
     let half_size = s.usr.rand_map.square_size as f32 / 2.0;
     let center = Vec2::new(final_size / 2.0, final_size / 2.0);
     let original_center = Vec2::new(half_size, half_size);
-    let screen_center = Vec2::new(screen_width() / 2.0, screen_height() / 2.0);
+    let screen_center = s.input.screen_size / 2.0;
     let tile_size = final_size / s.usr.rand_map.square_size as f32;
     let d = s.textures[&Textures::empty];
     let d_size = d.width();
@@ -439,7 +531,7 @@ fn fill_generated_map(s: &mut GameState, _dt: f32) {
         for (entity, _) in s.world.query_mut::<&BuildingMapTile>() {
             cb.despawn(entity);
         }
-        s.usr.voronoi_tiling.ready_to_tile = true;
+        s.set_app_state(Phase::TilingRegions);
         // s.usr.recursive_tiling.ready_to_tile = true;
         cb.run_on(&mut s.world);
     }
@@ -479,8 +571,18 @@ fn fill_generated_map(s: &mut GameState, _dt: f32) {
 
 #[macroquad::main("battlegame")]
 async fn main() {
-    let systems = get_all_systems();
-    let state: State<MyState, Textures> = State::new();
+    let schedule = get_schedule();
+    let mut state: State<MyState, Textures> = State::new();
+    state.seed = 7;
+    let mut args = std::env::args().skip(1);
+    match (args.next().as_deref(), args.next()) {
+        (Some("--record"), _) => state.start_recording(),
+        (Some("--replay"), Some(path)) => match InputRecording::load_from_file(&path) {
+            Ok(recording) => state.start_replay(recording),
+            Err(e) => warn!("failed to load input recording {}: {}", path, e),
+        },
+        _ => {}
+    }
 
-    run(state, systems, 100).await;
+    run(state, schedule, 100).await;
 }
@@ -5,29 +5,61 @@
 use hecs::*;
 use macroquad::prelude::*;
 
-use crate::data::{
-    world::*,
-    loading::*,
+use crate::{
+    data::{
+        world::*,
+        loading::*,
+    },
+    system::stateful::*,
 };
 
 pub struct Tint {
     pub d: Color,
 }
 
+/// drawn by `draw` in the entity's layer. the points are relative to the entity's `Transform`.
+/// like shape drawables, the entity's `Tint` is multiplied with the paint.
 pub struct Triangle {
     pub a: Vec2,
     pub b: Vec2,
     pub c: Vec2,
+    pub paint: Paint,
 }
 impl Triangle {
+    pub fn new(a: Vec2, b: Vec2, c: Vec2, paint: Paint) -> Self {
+        Self { a, b, c, paint }
+    }
+    /// filled with white, so that the color is the entity's `Tint`
+    pub fn filled(a: Vec2, b: Vec2, c: Vec2) -> Self {
+        Self::new(a, b, c, Paint::fill(WHITE))
+    }
+    /// outlined with white, so that the color is the entity's `Tint`
+    pub fn outline(a: Vec2, b: Vec2, c: Vec2, width: f32) -> Self {
+        Self::new(a, b, c, Paint::stroke(WHITE, width))
+    }
     pub fn center(&self) -> Vec2 {
         (self.a + self.b + self.c) / 3.0
     }
 }
 
+/// drawn by `draw` in the entity's layer. the points are relative to the entity's `Transform`.
+/// like shape drawables, the entity's `Tint` is multiplied with the color.
 pub struct LineSegment {
     pub a: Vec2,
     pub b: Vec2,
+    /// in world units
+    pub width: f32,
+    pub color: Color,
+}
+impl LineSegment {
+    /// a white line, so that the color is the entity's `Tint`
+    pub fn new(a: Vec2, b: Vec2, width: f32) -> Self {
+        Self { a, b, width, color: WHITE }
+    }
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -53,14 +85,89 @@ pub struct Parent {
     pub local_transform: Transform,
 }
 
+/// the entities whose `Parent` is this entity, in the order they were attached.
+/// maintained automatically by `set_parent`, `detach`, `despawn_recursive`
+/// and `update_children_transforms`, so it should not be modified directly.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Children {
+    pub d: Vec<Entity>,
+}
+
 /// unit struct to hide entities from being drawn
 pub struct Hidden;
 
-/// represents anything drawable. currently just limitied to single textures
-/// but can be expanded to include shapes, animations, text, etc.
-#[derive(Clone, Copy)]
+/// the colors of a shape. a shape without a fill is only outlined,
+/// and a shape without a stroke is only filled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Paint {
+    pub fill: Option<Color>,
+    pub stroke: Option<Color>,
+    /// in world units
+    pub stroke_width: f32,
+}
+impl Paint {
+    pub fn fill(color: Color) -> Self {
+        Self { fill: Some(color), stroke: None, stroke_width: 0.0 }
+    }
+    pub fn stroke(color: Color, width: f32) -> Self {
+        Self { fill: None, stroke: Some(color), stroke_width: width }
+    }
+    pub fn with_stroke(mut self, color: Color, width: f32) -> Self {
+        self.stroke = Some(color);
+        self.stroke_width = width;
+        self
+    }
+}
+
+/// represents anything drawable. textures are drawn centered on the entity's transform,
+/// unless dont_center is set. shapes are in world units, relative to the entity's transform.
+/// if the entity has a `Tint`, it is multiplied with the colors of shapes.
+#[derive(Clone, Debug)]
 pub enum Drawable {
     Texture { d: Texture2D, dont_center: bool },
+    /// the current frame of a sprite sheet animation, drawn like a texture.
+    /// see: `advance_animations`
+    Animation { d: SpriteAnimation, dont_center: bool },
+    /// centered on the transform
+    Circle { radius: f32, paint: Paint },
+    /// centered on the transform
+    Rect { size: Vec2, paint: Paint },
+    /// a closed shape. the fill is only correct for convex polygons.
+    Polygon { points: Vec<Vec2>, paint: Paint },
+    /// an open line through the points
+    Polyline { points: Vec<Vec2>, color: Color, width: f32 },
+    /// a single line of text, aligned horizontally around the transform and vertically centered on it.
+    /// see: `Drawable::text`
+    Text {
+        text: String,
+        /// None uses macroquad's default font
+        font: Option<Font>,
+        /// in world units in world space, in pixels in screen space
+        size: f32,
+        align: TextAlign,
+        color: Color,
+        /// drawn around each letter, if set
+        outline: Option<Color>,
+        /// in the same units as size
+        outline_width: f32,
+        space: TextSpace,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextSpace {
+    /// the text scales and rotates with the camera and the entity's transform, eg: region labels
+    World,
+    /// the text follows the entity's position, but is always upright and the same
+    /// number of pixels large, eg: floating damage numbers
+    Screen,
 }
 impl Drawable {
     pub fn texture<U: UserState<T>, T: TextureEnum>(s: &State<U, T>, t: T) -> Self {
@@ -69,8 +176,197 @@ impl Drawable {
         } else { Texture2D::empty() };
         Self::Texture { d, dont_center: false }
     }
+    pub fn animation(d: SpriteAnimation) -> Self {
+        Self::Animation { d, dont_center: false }
+    }
+    /// centered, white world space text without an outline
+    pub fn text(text: impl Into<String>, size: f32) -> Self {
+        Self::Text {
+            text: text.into(),
+            font: None,
+            size,
+            align: TextAlign::Center,
+            color: WHITE,
+            outline: None,
+            outline_width: 0.0,
+            space: TextSpace::World,
+        }
+    }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationMode {
+    /// starts over from the first frame after the last frame
+    Loop,
+    /// plays forwards, then backwards, then forwards again, etc.
+    PingPong,
+    /// stops on the last frame
+    Once,
+}
+
+/// frames of a sprite sheet, and how long each of them is shown. Example:
+/// ```
+/// // a 4x2 grid of frames, played once at 10 frames per second
+/// let explosion = SpriteAnimation::grid(s, Textures::explosion, 4, 2, 0.1).with_mode(AnimationMode::Once);
+/// s.world.spawn((Transform::default(), Drawable::animation(explosion), Layer3));
+/// ```
+#[derive(Clone, Debug)]
+pub struct SpriteAnimation {
+    pub sheet: Texture2D,
+    /// the part of the sheet that each frame is drawn from, in pixels
+    pub frames: Vec<Rect>,
+    /// how many seconds each frame is shown. frames without a duration use the last duration.
+    pub durations: Vec<f32>,
+    pub mode: AnimationMode,
+    frame: usize,
+    /// seconds the current frame has been shown
+    elapsed: f32,
+    /// true while a ping pong animation plays backwards
+    backwards: bool,
+    finished: bool,
+}
+
+impl SpriteAnimation {
+    /// a looping animation of the given frames of the sheet
+    pub fn new<U: UserState<T>, T: TextureEnum>(s: &State<U, T>, sheet: T, frames: Vec<Rect>, frame_duration: f32) -> Self {
+        let sheet = if let Some(t) = s.textures.get(&sheet) {
+            *t
+        } else { Texture2D::empty() };
+        Self::from_texture(sheet, frames, frame_duration)
+    }
+    /// a looping animation of a sheet split into a grid of equally sized frames, left to right, top to bottom
+    pub fn grid<U: UserState<T>, T: TextureEnum>(s: &State<U, T>, sheet: T, columns: usize, rows: usize, frame_duration: f32) -> Self {
+        let mut animation = Self::new(s, sheet, vec![], frame_duration);
+        let size = Vec2::new(animation.sheet.width() / columns.max(1) as f32, animation.sheet.height() / rows.max(1) as f32);
+        for row in 0..rows {
+            for column in 0..columns {
+                animation.frames.push(Rect::new(column as f32 * size.x, row as f32 * size.y, size.x, size.y));
+            }
+        }
+        animation
+    }
+    /// same as `new`, for sheets that were generated at runtime
+    pub fn from_texture(sheet: Texture2D, frames: Vec<Rect>, frame_duration: f32) -> Self {
+        Self {
+            sheet,
+            frames,
+            durations: vec![frame_duration],
+            mode: AnimationMode::Loop,
+            frame: 0,
+            elapsed: 0.0,
+            backwards: false,
+            finished: false,
+        }
+    }
+    pub fn with_mode(mut self, mode: AnimationMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    /// a duration per frame, in seconds
+    pub fn with_durations(mut self, durations: Vec<f32>) -> Self {
+        self.durations = durations;
+        self
+    }
+    /// the index of the frame that is shown
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+    /// the part of the sheet to draw, or None if there are no frames
+    pub fn source_rect(&self) -> Option<Rect> {
+        self.frames.get(self.frame).copied()
+    }
+    pub fn duration(&self, frame: usize) -> f32 {
+        self.durations.get(frame).or(self.durations.last()).copied().unwrap_or(0.0)
+    }
+    /// true once a `Once` animation reached its last frame
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+    /// jumps to the start of the frame, and plays forwards from there
+    pub fn set_frame(&mut self, frame: usize) {
+        self.frame = frame.min(self.frames.len().saturating_sub(1));
+        self.elapsed = 0.0;
+        self.backwards = false;
+        self.finished = false;
+    }
+    /// plays from the first frame again
+    pub fn restart(&mut self) {
+        self.set_frame(0);
+    }
+    /// how long a frame is shown while advancing. frames of 0 seconds would never let the animation end
+    fn step_duration(&self, frame: usize) -> f32 {
+        self.duration(frame).max(0.001)
+    }
+    /// seconds from the first frame back to the first frame, when looping or ping ponging
+    pub fn cycle_duration(&self) -> f32 {
+        let count = self.frames.len();
+        let forwards: f32 = (0..count).map(|f| self.step_duration(f)).sum();
+        match self.mode {
+            // the first and last frames are only shown once per cycle
+            AnimationMode::PingPong => forwards + (1..count.saturating_sub(1)).map(|f| self.step_duration(f)).sum::<f32>(),
+            AnimationMode::Loop | AnimationMode::Once => forwards,
+        }
+    }
+    /// advances by dt seconds, skipping frames if dt is longer than a frame.
+    /// returns how many cycles a `Loop` or `PingPong` animation completed,
+    /// or 1 if a `Once` animation finished.
+    pub fn advance(&mut self, dt: f32) -> usize {
+        let count = self.frames.len();
+        if self.finished || count == 0 || !dt.is_finite() {
+            return 0;
+        }
+        self.elapsed += dt;
+        let mut completed = 0;
+        if self.mode != AnimationMode::Once {
+            // skip whole cycles at once, so that a long dt doesnt step through every frame.
+            // a whole cycle ends on the frame it started on
+            let cycle = self.cycle_duration();
+            if self.elapsed >= cycle {
+                completed += (self.elapsed / cycle) as usize;
+                self.elapsed %= cycle;
+            }
+        }
+        loop {
+            let duration = self.step_duration(self.frame);
+            if self.elapsed < duration {
+                return completed;
+            }
+            self.elapsed -= duration;
+            match self.mode {
+                AnimationMode::Loop => {
+                    self.frame += 1;
+                    if self.frame >= count {
+                        self.frame = 0;
+                        completed += 1;
+                    }
+                }
+                AnimationMode::Once => {
+                    if self.frame + 1 >= count {
+                        self.elapsed = 0.0;
+                        self.finished = true;
+                        return 1;
+                    }
+                    self.frame += 1;
+                }
+                AnimationMode::PingPong if count == 1 => completed += 1,
+                AnimationMode::PingPong => {
+                    if self.frame + 1 >= count {
+                        self.backwards = true;
+                    }
+                    if self.backwards {
+                        self.frame -= 1;
+                        if self.frame == 0 {
+                            self.backwards = false;
+                            completed += 1;
+                        }
+                    } else {
+                        self.frame += 1;
+                    }
+                }
+            }
+        }
+    }
+}
 
 /// unit structs representing which layer objects should be drawn on
 pub struct Layer0;
@@ -82,4 +378,184 @@ pub struct Layer5;
 pub struct Layer6;
 pub struct Layer7;
 pub struct Layer8;
-pub struct Layer9;
\ No newline at end of file
+pub struct Layer9;
+
+/// a set of layers, as bits where bit N is LayerN
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LayerMask(pub u16);
+impl Default for LayerMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+impl LayerMask {
+    pub const ALL: LayerMask = LayerMask(0b11_1111_1111);
+    pub const NONE: LayerMask = LayerMask(0);
+    /// the number of layers, Layer0 to Layer9
+    pub const LAYER_COUNT: u8 = 10;
+
+    /// the mask with the layer added, eg: `LayerMask::NONE.with(0).with(6)`.
+    /// panics if the layer is not below `LAYER_COUNT`
+    pub fn with(self, layer: u8) -> Self {
+        Self(self.0 | Self::bit(layer))
+    }
+    /// the mask with the layer removed, eg: `LayerMask::ALL.without(9)`.
+    /// panics if the layer is not below `LAYER_COUNT`
+    pub fn without(self, layer: u8) -> Self {
+        Self(self.0 & !Self::bit(layer))
+    }
+    /// false for layers that dont exist
+    pub fn contains(&self, layer: u8) -> bool {
+        layer < Self::LAYER_COUNT && self.0 & Self::bit(layer) != 0
+    }
+    fn bit(layer: u8) -> u16 {
+        assert!(layer < Self::LAYER_COUNT, "layer {} does not exist, there are {} layers", layer, Self::LAYER_COUNT);
+        1 << layer
+    }
+}
+
+/// an additional view of the world, drawn to a part of the screen after the main view (`State::coords`).
+/// spawn it as an entity, eg: a minimap in the top right corner that only draws the map layers:
+/// ```
+/// let viewport = Rect::new(screen_width() - 210.0, 10.0, 200.0, 200.0);
+/// let mut minimap = CameraView::new(viewport, LayerMask::NONE.with(0).with(6));
+/// minimap.coords.fit_rect(map_rect, 0.0);
+/// s.world.spawn((minimap,));
+/// ```
+pub struct CameraView {
+    /// the pan, scale and rotation of this view. its viewport_size is the size of the viewport.
+    pub coords: CoordTransform,
+    /// where on the screen to draw, in screen pixels
+    pub viewport: Rect,
+    /// which layers this camera draws
+    pub layers: LayerMask,
+    /// cameras are drawn in ascending order
+    pub order: i32,
+    /// if set, the viewport is filled with this color before drawing the layers
+    pub clear_color: Option<Color>,
+}
+impl CameraView {
+    pub fn new(viewport: Rect, layers: LayerMask) -> Self {
+        Self {
+            coords: CoordTransform::default().with_viewport_size(viewport.size()),
+            viewport,
+            layers,
+            order: 0,
+            clear_color: None,
+        }
+    }
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+    pub fn with_clear_color(mut self, clear_color: Color) -> Self {
+        self.clear_color = Some(clear_color);
+        self
+    }
+    pub fn set_viewport(&mut self, viewport: Rect) {
+        self.viewport = viewport;
+        self.coords.viewport_size = viewport.size();
+    }
+    /// converts world coordinates to screen coordinates, including the viewport's offset
+    pub fn view_matrix(&self) -> Affine2 {
+        Affine2::from_translation(self.viewport.point()) * self.coords.view_matrix()
+    }
+    pub fn to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        self.view_matrix().transform_point2(Vec2::new(x, y)).into()
+    }
+    /// the screen point is relative to the whole screen, not to the viewport
+    pub fn to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        self.coords.to_world(x - self.viewport.x, y - self.viewport.y)
+    }
+    /// true if the screen point is within the viewport, eg: to pick with the right camera
+    pub fn contains_screen_point(&self, x: f32, y: f32) -> bool {
+        self.viewport.contains(Vec2::new(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(frames: usize, mode: AnimationMode) -> SpriteAnimation {
+        let frames = (0..frames).map(|i| Rect::new(i as f32 * 8.0, 0.0, 8.0, 8.0)).collect();
+        SpriteAnimation::from_texture(Texture2D::empty(), frames, 0.25).with_mode(mode)
+    }
+
+    /// the frame after each advance by dt, and the cycles completed
+    fn play(animation: &mut SpriteAnimation, dt: f32, times: usize) -> Vec<(usize, usize)> {
+        (0..times).map(|_| {
+            let completed = animation.advance(dt);
+            (animation.frame(), completed)
+        }).collect()
+    }
+
+    #[test]
+    fn loop_wraps_around() {
+        let mut a = animation(3, AnimationMode::Loop);
+        assert_eq!(play(&mut a, 0.25, 4), vec![(1, 0), (2, 0), (0, 1), (1, 0)]);
+        assert_eq!(a.source_rect(), Some(Rect::new(8.0, 0.0, 8.0, 8.0)));
+    }
+
+    #[test]
+    fn ping_pong_reverses_at_the_ends() {
+        let mut a = animation(3, AnimationMode::PingPong);
+        assert_eq!(play(&mut a, 0.25, 5), vec![(1, 0), (2, 0), (1, 0), (0, 1), (1, 0)]);
+        assert_eq!(a.cycle_duration(), 1.0);
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        let mut a = animation(3, AnimationMode::Once);
+        assert_eq!(play(&mut a, 0.25, 4), vec![(1, 0), (2, 0), (2, 1), (2, 0)]);
+        assert!(a.is_finished());
+        a.restart();
+        assert!(!a.is_finished());
+        assert_eq!(a.frame(), 0);
+    }
+
+    #[test]
+    fn per_frame_durations() {
+        // the last duration is used for frames without one
+        let mut a = animation(3, AnimationMode::Loop).with_durations(vec![0.25, 0.5]);
+        assert_eq!(play(&mut a, 0.25, 5), vec![(1, 0), (1, 0), (2, 0), (2, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn long_frames_skip_whole_cycles() {
+        let mut a = animation(3, AnimationMode::Loop);
+        // 40001 cycles of 0.75 seconds, and one more frame
+        assert_eq!(a.advance(30_001.0), 40_001);
+        assert_eq!(a.frame(), 1);
+
+        let mut a = animation(3, AnimationMode::PingPong).with_durations(vec![0.0]);
+        a.advance(1.0e9);
+        assert!(a.frame() < 3);
+
+        let mut a = animation(3, AnimationMode::Once);
+        assert_eq!(a.advance(1.0e9), 1);
+        assert_eq!(a.frame(), 2);
+    }
+
+    #[test]
+    fn no_frames_does_nothing() {
+        let mut a = animation(0, AnimationMode::Loop);
+        assert_eq!(a.advance(1.0), 0);
+        assert_eq!(a.source_rect(), None);
+    }
+
+    #[test]
+    fn layer_mask_bits() {
+        let mask = LayerMask::NONE.with(0).with(9);
+        assert_eq!(mask, LayerMask(0b10_0000_0001));
+        assert!(mask.contains(9) && !mask.contains(5));
+        assert_eq!(LayerMask::ALL.without(0).without(9), LayerMask(0b01_1111_1110));
+        assert!(!LayerMask::ALL.contains(10) && !LayerMask::ALL.contains(255));
+    }
+
+    #[test]
+    #[should_panic]
+    fn layer_mask_rejects_missing_layers() {
+        LayerMask::NONE.with(16);
+    }
+}
@@ -1,2 +1,9 @@
 pub mod loading;
-pub mod world;
\ No newline at end of file
+pub mod world;
+pub mod events;
+pub mod resources;
+pub mod app_state;
+pub mod time;
+pub mod save;
+pub mod input;
+pub mod actions;
\ No newline at end of file
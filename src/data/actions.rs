@@ -0,0 +1,335 @@
+//! Actions decouple what a system wants to know ("is the player panning left?")
+//! from the keys and buttons that trigger it. Every action can be bound to any number of
+//! keys, mouse buttons, or mouse wheel directions, and the bindings can be changed at runtime
+//! or loaded from a config file. Systems query actions via `State::action_down` etc:
+//! ```
+//! const PAUSE: Action = Action::Custom("Pause");
+//! s.actions.bind(PAUSE, Binding::Key(KeyCode::Space));
+//! // in a system:
+//! if s.action_pressed(PAUSE) {
+//!     s.time.toggle_pause();
+//! }
+//! ```
+//! The config file has a line per action, with the action name and a comma separated list of bindings:
+//! ```text
+//! # lines starting with # are ignored
+//! PanLeft = Key(Left), Key(A)
+//! ZoomIn = WheelUp, Key(Equal)
+//! Pause = Key(Space)
+//! ```
+
+use std::io::{Error, ErrorKind};
+
+use macroquad::prelude::*;
+
+use crate::data::input::*;
+
+/// a named action. the builtin actions are used by the builtin systems, eg: `handle_pan`.
+/// games can define their own with `Action::Custom`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    /// pans the camera by dragging the mouse while held
+    PanDrag,
+    ZoomIn,
+    ZoomOut,
+    /// rotates the view counter clockwise
+    RotateLeft,
+    /// rotates the view clockwise
+    RotateRight,
+    Select,
+    Custom(&'static str),
+}
+
+impl Action {
+    pub const BUILTIN: [Action; 10] = [
+        Action::PanLeft,
+        Action::PanRight,
+        Action::PanUp,
+        Action::PanDown,
+        Action::PanDrag,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::RotateLeft,
+        Action::RotateRight,
+        Action::Select,
+    ];
+
+    /// the name used in config files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::PanLeft => "PanLeft",
+            Action::PanRight => "PanRight",
+            Action::PanUp => "PanUp",
+            Action::PanDown => "PanDown",
+            Action::PanDrag => "PanDrag",
+            Action::ZoomIn => "ZoomIn",
+            Action::ZoomOut => "ZoomOut",
+            Action::RotateLeft => "RotateLeft",
+            Action::RotateRight => "RotateRight",
+            Action::Select => "Select",
+            Action::Custom(name) => name,
+        }
+    }
+}
+
+/// an input that can trigger an action
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// the mouse wheel scrolled up this frame. counts as both down and pressed, never as released.
+    WheelUp,
+    /// the mouse wheel scrolled down this frame. counts as both down and pressed, never as released.
+    WheelDown,
+}
+
+impl Binding {
+    pub fn is_down(&self, input: &InputState) -> bool {
+        match self {
+            Binding::Key(key) => input.is_key_down(*key),
+            Binding::Mouse(button) => input.is_mouse_button_down(*button),
+            Binding::WheelUp => input.mouse_wheel.y > 0.0,
+            Binding::WheelDown => input.mouse_wheel.y < 0.0,
+        }
+    }
+    pub fn is_pressed(&self, input: &InputState) -> bool {
+        match self {
+            Binding::Key(key) => input.is_key_pressed(*key),
+            Binding::Mouse(button) => input.is_mouse_button_pressed(*button),
+            Binding::WheelUp | Binding::WheelDown => self.is_down(input),
+        }
+    }
+    pub fn is_released(&self, input: &InputState) -> bool {
+        match self {
+            Binding::Key(key) => input.is_key_released(*key),
+            Binding::Mouse(button) => input.is_mouse_button_released(*button),
+            Binding::WheelUp | Binding::WheelDown => false,
+        }
+    }
+    /// parses the format written by Debug, eg: `Key(Left)`, `Mouse(Right)`, `WheelUp`
+    pub fn parse(data: &str) -> Option<Self> {
+        let data = data.trim();
+        match data {
+            "WheelUp" => return Some(Binding::WheelUp),
+            "WheelDown" => return Some(Binding::WheelDown),
+            _ => {}
+        }
+        let (kind, name) = data.strip_suffix(')')?.split_once('(')?;
+        match kind.trim() {
+            "Key" => key_from_name(name.trim()).map(Binding::Key),
+            "Mouse" => mouse_button_from_name(name.trim()).map(Binding::Mouse),
+            _ => None,
+        }
+    }
+}
+
+/// the bindings of every action. stored in `State::actions`.
+/// the same binding can trigger multiple actions, eg: by default the left mouse button
+/// triggers both `PanDrag` and `Select`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActionMap {
+    bindings: Vec<(Action, Vec<Binding>)>,
+}
+
+impl Default for ActionMap {
+    /// the default bindings of the builtin actions
+    fn default() -> Self {
+        let mut map = Self::new();
+        map.bind(Action::PanLeft, Binding::Key(KeyCode::Left));
+        map.bind(Action::PanRight, Binding::Key(KeyCode::Right));
+        map.bind(Action::PanUp, Binding::Key(KeyCode::Up));
+        map.bind(Action::PanDown, Binding::Key(KeyCode::Down));
+        map.bind(Action::PanDrag, Binding::Mouse(MouseButton::Left));
+        map.bind(Action::ZoomIn, Binding::WheelUp);
+        map.bind(Action::ZoomOut, Binding::WheelDown);
+        map.bind(Action::RotateLeft, Binding::Key(KeyCode::Q));
+        map.bind(Action::RotateRight, Binding::Key(KeyCode::E));
+        map.bind(Action::Select, Binding::Mouse(MouseButton::Left));
+        map
+    }
+}
+
+impl ActionMap {
+    /// a map without any bindings
+    pub fn new() -> Self {
+        Self { bindings: vec![] }
+    }
+    fn entry(&mut self, action: Action) -> &mut Vec<Binding> {
+        let index = match self.bindings.iter().position(|(a, _)| *a == action) {
+            Some(index) => index,
+            None => {
+                self.bindings.push((action, vec![]));
+                self.bindings.len() - 1
+            }
+        };
+        &mut self.bindings[index].1
+    }
+    /// adds a binding to the action, keeping its existing bindings
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.entry(action);
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+    pub fn unbind(&mut self, action: Action, binding: Binding) {
+        self.entry(action).retain(|b| *b != binding);
+    }
+    /// replaces every binding of the action
+    pub fn rebind(&mut self, action: Action, bindings: impl IntoIterator<Item = Binding>) {
+        let existing = self.entry(action);
+        existing.clear();
+        for binding in bindings {
+            if !existing.contains(&binding) {
+                existing.push(binding);
+            }
+        }
+    }
+    /// removes every binding of the action. the action is still known,
+    /// so it can be bound again from a config file.
+    pub fn clear(&mut self, action: Action) {
+        self.entry(action).clear();
+    }
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        match self.bindings.iter().find(|(a, _)| *a == action) {
+            Some((_, bindings)) => bindings,
+            None => &[],
+        }
+    }
+    /// every action that was ever bound, in the order they were first bound
+    pub fn actions(&self) -> impl Iterator<Item = Action> + '_ {
+        self.bindings.iter().map(|(a, _)| *a)
+    }
+    pub fn is_down(&self, action: Action, input: &InputState) -> bool {
+        self.bindings(action).iter().any(|b| b.is_down(input))
+    }
+    pub fn is_pressed(&self, action: Action, input: &InputState) -> bool {
+        self.bindings(action).iter().any(|b| b.is_pressed(input))
+    }
+    pub fn is_released(&self, action: Action, input: &InputState) -> bool {
+        self.bindings(action).iter().any(|b| b.is_released(input))
+    }
+    /// the first key, mouse button, or wheel direction that was pressed this frame.
+    /// useful for "press a key to rebind" menus, eg:
+    /// ```
+    /// if let Some(binding) = ActionMap::pressed_binding(&s.input) {
+    ///     s.actions.rebind(Action::Select, [binding]);
+    /// }
+    /// ```
+    pub fn pressed_binding(input: &InputState) -> Option<Binding> {
+        if let Some(key) = ALL_KEY_CODES.iter().find(|k| input.is_key_pressed(**k)) {
+            return Some(Binding::Key(*key));
+        }
+        if let Some(button) = ALL_MOUSE_BUTTONS.iter().find(|b| input.is_mouse_button_pressed(**b)) {
+            return Some(Binding::Mouse(*button));
+        }
+        [Binding::WheelUp, Binding::WheelDown].into_iter().find(|b| b.is_pressed(input))
+    }
+
+    /// every action and its bindings in the config file format
+    pub fn save(&self) -> String {
+        let mut out = String::new();
+        for (action, bindings) in self.bindings.iter() {
+            let bindings: Vec<_> = bindings.iter().map(|b| format!("{:?}", b)).collect();
+            out.push_str(&format!("{} = {}\n", action.name(), bindings.join(", ")));
+        }
+        out
+    }
+    /// replaces the bindings of every action in the config. actions that are
+    /// not in the config keep their bindings. the config can only refer to builtin actions
+    /// and custom actions that were already bound, as custom action names are not known otherwise.
+    pub fn load(&mut self, data: &str) -> Result<(), Error> {
+        let invalid = |line: usize, msg: String| Error::new(ErrorKind::InvalidData, format!("line {}: {}", line + 1, msg));
+        // parse everything first, so that an invalid config doesnt leave the map half loaded
+        let mut parsed = vec![];
+        for (line_index, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, bindings) = line.split_once('=')
+                .ok_or_else(|| invalid(line_index, "expected: <action> = <bindings>".to_string()))?;
+            let name = name.trim();
+            let action = Action::BUILTIN.into_iter().chain(self.actions())
+                .find(|a| a.name() == name)
+                .ok_or_else(|| invalid(line_index, format!("unknown action {}", name)))?;
+            let mut parsed_bindings = vec![];
+            for binding in bindings.split(',').filter(|b| !b.trim().is_empty()) {
+                let binding = Binding::parse(binding)
+                    .ok_or_else(|| invalid(line_index, format!("invalid binding {}", binding.trim())))?;
+                parsed_bindings.push(binding);
+            }
+            parsed.push((action, parsed_bindings));
+        }
+        for (action, bindings) in parsed {
+            self.rebind(action, bindings);
+        }
+        Ok(())
+    }
+    pub fn save_to_file(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.save())
+    }
+    pub fn load_from_file(&mut self, path: &str) -> std::io::Result<()> {
+        let data = std::fs::read_to_string(path)?;
+        self.load(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bindings() {
+        assert_eq!(Binding::parse(" Key(Left) "), Some(Binding::Key(KeyCode::Left)));
+        assert_eq!(Binding::parse("Key( A )"), Some(Binding::Key(KeyCode::A)));
+        assert_eq!(Binding::parse("Mouse(Right)"), Some(Binding::Mouse(MouseButton::Right)));
+        assert_eq!(Binding::parse("WheelDown"), Some(Binding::WheelDown));
+        for data in ["Key(Nope)", "Mouse(A)", "Pad(A)", "Key(Left", "Left", ""] {
+            assert_eq!(Binding::parse(data), None, "{}", data);
+        }
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        const JUMP: Action = Action::Custom("Jump");
+        let mut actions = ActionMap::default();
+        actions.bind(JUMP, Binding::Key(KeyCode::Space));
+        actions.bind(Action::ZoomIn, Binding::Key(KeyCode::Equal));
+        actions.clear(Action::Select);
+        let mut loaded = ActionMap::new();
+        loaded.bind(JUMP, Binding::WheelUp);
+        loaded.load(&actions.save()).unwrap();
+        for action in actions.actions() {
+            assert_eq!(loaded.bindings(action), actions.bindings(action), "{:?}", action);
+        }
+        assert_eq!(loaded.actions().count(), actions.actions().count());
+    }
+
+    #[test]
+    fn unknown_names_change_nothing() {
+        for data in ["PanLeft = Key(Nope)", "Nope = Key(A)", "PanLeft = Key(A)\nPanRight Key(B)"] {
+            let mut actions = ActionMap::default();
+            let err = actions.load(data).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            assert_eq!(actions, ActionMap::default(), "{}", data);
+        }
+    }
+
+    #[test]
+    fn rebind_replaces_every_binding() {
+        let mut actions = ActionMap::default();
+        actions.bind(Action::PanLeft, Binding::Key(KeyCode::A));
+        assert_eq!(actions.bindings(Action::PanLeft).len(), 2);
+        actions.rebind(Action::PanLeft, [Binding::Key(KeyCode::H), Binding::Key(KeyCode::H)]);
+        assert_eq!(actions.bindings(Action::PanLeft), &[Binding::Key(KeyCode::H)]);
+        let mut input = InputState::default();
+        input.keys_down.insert(KeyCode::A);
+        assert!(!actions.is_down(Action::PanLeft, &input));
+        input.keys_down.insert(KeyCode::H);
+        assert!(actions.is_down(Action::PanLeft, &input));
+    }
+}
@@ -0,0 +1,62 @@
+//! An app state is a user defined enum that represents the phase the game is in,
+//! eg: generating terrain, tiling regions, playing. The current app state is stored
+//! as a resource, and the schedule only runs the systems registered for the current state.
+//! See: `Schedule::with_app_state`, `Schedule::on_enter`, `Schedule::on_exit`, `Schedule::on_update`.
+
+use std::{fmt::Debug, hash::Hash};
+
+/// any enum that can be used as an app state.
+/// implemented automatically for every type that satisfies the bounds.
+pub trait AppStateEnum: Copy + Eq + Hash + Debug + 'static {}
+impl<S: Copy + Eq + Hash + Debug + 'static> AppStateEnum for S {}
+
+/// the resource that holds the current app state.
+/// transitions are not applied immediately, but at the start
+/// of the next simulation step, so that every system in a frame sees the same state.
+pub struct AppState<S: AppStateEnum> {
+    current: S,
+    next: Option<S>,
+    entered: bool,
+}
+
+impl<S: AppStateEnum> AppState<S> {
+    pub fn new(initial: S) -> Self {
+        Self {
+            current: initial,
+            next: None,
+            entered: false,
+        }
+    }
+    pub fn current(&self) -> S {
+        self.current
+    }
+    /// the state that will be transitioned to at the start of the next simulation step, if any.
+    pub fn next(&self) -> Option<S> {
+        self.next
+    }
+    /// queues a transition to the given state. if called multiple times
+    /// before the transition is applied, only the last state is used.
+    pub fn set(&mut self, next: S) {
+        self.next = Some(next);
+    }
+    /// returns the (exited, entered) states if there is a transition to apply.
+    /// the first call returns (None, initial state) so that the initial state is entered.
+    /// setting the state to the current state is not considered a transition.
+    pub fn apply_transition(&mut self) -> Option<(Option<S>, S)> {
+        if !self.entered {
+            self.entered = true;
+            // a transition queued before the initial state was entered replaces it
+            if let Some(next) = self.next.take() {
+                self.current = next;
+            }
+            return Some((None, self.current));
+        }
+        let next = self.next.take()?;
+        if next == self.current {
+            return None;
+        }
+        let previous = self.current;
+        self.current = next;
+        Some((Some(previous), next))
+    }
+}
@@ -0,0 +1,205 @@
+//! Typed event queues that let systems communicate without adding flags to the user state.
+//! An event sent during a frame can be read for the rest of that frame and during the next frame,
+//! after which it is cleared. Events sent by the simulation stages are additionally kept until
+//! the next simulation step ended, so that with a fixed timestep they are not lost on frames
+//! that run no steps. Events are stored in `State::events`. Example:
+//! ```
+//! pub struct MapGenerated;
+//!
+//! fn fill_generated_map(s: &mut GameState, _dt: f32) {
+//!     s.events.send(MapGenerated);
+//! }
+//! fn on_map_generated(s: &mut GameState, _dt: f32) {
+//!     if s.events.iter::<MapGenerated>().next().is_some() {
+//!         // ...
+//!     }
+//! }
+//! ```
+
+use std::{any::{Any, TypeId}, collections::HashMap, marker::PhantomData};
+
+/// a queue of events of a single type.
+/// each event is tagged with an id so that an `EventReader`
+/// can track which events it has already seen.
+pub struct Events<E> {
+    events: Vec<SentEvent<E>>,
+    next_id: usize,
+    /// true while the simulation stages run, see: `EventQueues::begin_step`
+    simulating: bool,
+}
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Self {
+            events: vec![],
+            next_id: 0,
+            simulating: false,
+        }
+    }
+}
+
+struct SentEvent<E> {
+    id: usize,
+    event: E,
+    /// how many more frame updates the event is kept for
+    frames: u8,
+    /// how many more simulation step updates the event is kept for
+    steps: u8,
+}
+
+impl<E> Events<E> {
+    /// events sent by a simulation system are kept until both the current and the next
+    /// simulation step and frame ended. other events are kept until the end of the next frame.
+    pub fn send(&mut self, event: E) {
+        let steps = if self.simulating { 2 } else { 0 };
+        self.events.push(SentEvent { id: self.next_id, event, frames: 2, steps });
+        self.next_id += 1;
+    }
+    /// iterates over all events that are still kept, oldest first.
+    /// events sent outside of the simulation stages, eg: by render systems, are kept until the end
+    /// of the next frame. events sent by simulation systems are kept until both the next frame and
+    /// the next simulation step ended, so frames that run no simulation step, eg: while the
+    /// simulation is paused, dont clear them.
+    /// a system can therefore see the same event on several frames,
+    /// use an `EventReader` to only see each event once.
+    pub fn iter(&self) -> impl Iterator<Item = &E> {
+        self.events.iter().map(|e| &e.event)
+    }
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// called at the end of every frame
+    pub fn update(&mut self) {
+        self.events.iter_mut().for_each(|e| e.frames = e.frames.saturating_sub(1));
+        self.events.retain(|e| e.frames > 0 || e.steps > 0);
+    }
+    /// called at the end of every simulation step
+    pub fn update_step(&mut self) {
+        self.events.iter_mut().for_each(|e| e.steps = e.steps.saturating_sub(1));
+        self.events.retain(|e| e.frames > 0 || e.steps > 0);
+    }
+}
+
+/// tracks which events have already been read, so that each event is only seen once.
+/// because systems can own local state, a reader can be kept inside of a closure system:
+/// ```
+/// let mut reader = EventReader::<TileHovered>::default();
+/// sys!("log_hovered", move |s, _dt| {
+///     for hovered in s.events.read(&mut reader) {
+///         // ...
+///     }
+/// })
+/// ```
+pub struct EventReader<E> {
+    next_id: usize,
+    _marker: PhantomData<fn() -> E>,
+}
+impl<E> Default for EventReader<E> {
+    fn default() -> Self {
+        Self { next_id: 0, _marker: PhantomData }
+    }
+}
+
+impl<E> EventReader<E> {
+    /// returns all events that this reader has not seen yet.
+    pub fn read<'a>(&mut self, events: &'a Events<E>) -> impl Iterator<Item = &'a E> {
+        let start = self.next_id;
+        self.next_id = events.next_id;
+        events.events.iter()
+            .filter(move |e| e.id >= start)
+            .map(|e| &e.event)
+    }
+}
+
+/// type erased interface so that all event queues
+/// can be updated without knowing their event types.
+trait AnyEvents {
+    fn update(&mut self);
+    fn update_step(&mut self);
+    fn set_simulating(&mut self, simulating: bool);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+impl<E: 'static> AnyEvents for Events<E> {
+    fn update(&mut self) {
+        Events::update(self);
+    }
+    fn update_step(&mut self) {
+        Events::update_step(self);
+    }
+    fn set_simulating(&mut self, simulating: bool) {
+        self.simulating = simulating;
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// all event queues, keyed by event type.
+/// a queue is created the first time an event of that type is sent.
+#[derive(Default)]
+pub struct EventQueues {
+    queues: HashMap<TypeId, Box<dyn AnyEvents>>,
+    simulating: bool,
+}
+
+impl EventQueues {
+    pub fn send<E: 'static>(&mut self, event: E) {
+        self.get_or_create::<E>().send(event);
+    }
+    pub fn get<E: 'static>(&self) -> Option<&Events<E>> {
+        self.queues.get(&TypeId::of::<E>())?.as_any().downcast_ref()
+    }
+    pub fn get_mut<E: 'static>(&mut self) -> Option<&mut Events<E>> {
+        self.queues.get_mut(&TypeId::of::<E>())?.as_any_mut().downcast_mut()
+    }
+    pub fn get_or_create<E: 'static>(&mut self) -> &mut Events<E> {
+        let simulating = self.simulating;
+        self.queues.entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(Events::<E> { simulating, ..Default::default() }))
+            .as_any_mut()
+            .downcast_mut()
+            .expect("event queue is keyed by its own type id")
+    }
+    /// iterates over all events of this type that are still kept: events sent during this or the
+    /// previous frame, and events sent by simulation systems before the last simulation step ended.
+    /// see: `Events::iter`
+    pub fn iter<E: 'static>(&self) -> impl Iterator<Item = &E> {
+        self.get::<E>().into_iter().flat_map(|events| events.iter())
+    }
+    /// returns all events of this type that the reader has not seen yet.
+    pub fn read<'a, E: 'static>(&'a self, reader: &mut EventReader<E>) -> impl Iterator<Item = &'a E> {
+        let events = self.get::<E>();
+        let read = events.map(|events| reader.read(events));
+        read.into_iter().flatten()
+    }
+    /// advances every event queue by one frame. called by the schedule at the end of every frame.
+    pub fn update(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.update();
+        }
+    }
+    /// events sent until `end_step` are kept until the next step ended.
+    /// called by the schedule before the simulation stages.
+    pub fn begin_step(&mut self) {
+        self.set_simulating(true);
+    }
+    /// advances every event queue by one simulation step. called by the schedule after the simulation stages.
+    pub fn end_step(&mut self) {
+        self.set_simulating(false);
+        for queue in self.queues.values_mut() {
+            queue.update_step();
+        }
+    }
+    fn set_simulating(&mut self, simulating: bool) {
+        self.simulating = simulating;
+        for queue in self.queues.values_mut() {
+            queue.set_simulating(simulating);
+        }
+    }
+}
@@ -0,0 +1,382 @@
+//! A snapshot of the input of a single frame, and recording/replaying that input.
+//! The run loop fills `State::input` from macroquad at the start of every frame, and
+//! systems read from it instead of calling macroquad's input functions directly.
+//! Without a window, the input can be filled by hand to drive systems, eg:
+//! ```
+//! let mut s = State::<MyState, Textures>::headless();
+//! s.input.set_mouse_position(400.0, 300.0);
+//! s.input.scroll(0.0, 1.0);
+//! handle_pan(&mut s, 0.016); // zooms in around (400, 300)
+//! s.input.next_frame();
+//! ```
+//! This also lets a recorded session be replayed exactly, including in a headless runner:
+//! ```
+//! // record:
+//! state.start_recording();
+//! // ... later, eg: in a system when a key is pressed:
+//! if let Some(recording) = s.stop_recording() {
+//!     recording.save_to_file("session.input")?;
+//! }
+//! // replay:
+//! let recording = InputRecording::load_from_file("session.input")?;
+//! let state = run_replay_headless(State::headless(), schedule, recording);
+//! ```
+
+use std::{collections::HashSet, fmt::Write, io::{Error, ErrorKind}};
+
+use macroquad::prelude::*;
+
+/// every key that is polled from macroquad. excludes KeyCode::Unknown
+pub const ALL_KEY_CODES: [KeyCode; 120] = [
+    KeyCode::Space, KeyCode::Apostrophe, KeyCode::Comma, KeyCode::Minus, KeyCode::Period, KeyCode::Slash,
+    KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5,
+    KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9, KeyCode::Semicolon, KeyCode::Equal,
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F,
+    KeyCode::G, KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L,
+    KeyCode::M, KeyCode::N, KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R,
+    KeyCode::S, KeyCode::T, KeyCode::U, KeyCode::V, KeyCode::W, KeyCode::X,
+    KeyCode::Y, KeyCode::Z, KeyCode::LeftBracket, KeyCode::Backslash, KeyCode::RightBracket, KeyCode::GraveAccent,
+    KeyCode::World1, KeyCode::World2, KeyCode::Escape, KeyCode::Enter, KeyCode::Tab, KeyCode::Backspace,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Right, KeyCode::Left, KeyCode::Down, KeyCode::Up,
+    KeyCode::PageUp, KeyCode::PageDown, KeyCode::Home, KeyCode::End, KeyCode::CapsLock, KeyCode::ScrollLock,
+    KeyCode::NumLock, KeyCode::PrintScreen, KeyCode::Pause, KeyCode::F1, KeyCode::F2, KeyCode::F3,
+    KeyCode::F4, KeyCode::F5, KeyCode::F6, KeyCode::F7, KeyCode::F8, KeyCode::F9,
+    KeyCode::F10, KeyCode::F11, KeyCode::F12, KeyCode::F13, KeyCode::F14, KeyCode::F15,
+    KeyCode::F16, KeyCode::F17, KeyCode::F18, KeyCode::F19, KeyCode::F20, KeyCode::F21,
+    KeyCode::F22, KeyCode::F23, KeyCode::F24, KeyCode::F25, KeyCode::Kp0, KeyCode::Kp1,
+    KeyCode::Kp2, KeyCode::Kp3, KeyCode::Kp4, KeyCode::Kp5, KeyCode::Kp6, KeyCode::Kp7,
+    KeyCode::Kp8, KeyCode::Kp9, KeyCode::KpDecimal, KeyCode::KpDivide, KeyCode::KpMultiply, KeyCode::KpSubtract,
+    KeyCode::KpAdd, KeyCode::KpEnter, KeyCode::KpEqual, KeyCode::LeftShift, KeyCode::LeftControl, KeyCode::LeftAlt,
+    KeyCode::LeftSuper, KeyCode::RightShift, KeyCode::RightControl, KeyCode::RightAlt, KeyCode::RightSuper, KeyCode::Menu,
+];
+pub const ALL_MOUSE_BUTTONS: [MouseButton; 3] = [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+
+/// the state of the keyboard and mouse during a single frame.
+/// the functions mirror macroquad's input functions of the same name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputState {
+    pub keys_down: HashSet<KeyCode>,
+    pub keys_pressed: HashSet<KeyCode>,
+    pub keys_released: HashSet<KeyCode>,
+    pub mouse_down: HashSet<MouseButton>,
+    pub mouse_pressed: HashSet<MouseButton>,
+    pub mouse_released: HashSet<MouseButton>,
+    /// in screen coordinates
+    pub mouse_position: Vec2,
+    pub mouse_wheel: Vec2,
+    /// the size of the window. systems should prefer this over `screen_width`/`screen_height`
+    /// so that they behave the same in a replay or without a window.
+    pub screen_size: Vec2,
+}
+
+impl InputState {
+    /// polls the current input from macroquad. requires a graphics context.
+    pub fn from_macroquad() -> Self {
+        let mut input = Self::default();
+        for key in ALL_KEY_CODES {
+            if is_key_down(key) {
+                input.keys_down.insert(key);
+            }
+            if is_key_pressed(key) {
+                input.keys_pressed.insert(key);
+            }
+            if is_key_released(key) {
+                input.keys_released.insert(key);
+            }
+        }
+        for button in ALL_MOUSE_BUTTONS {
+            if is_mouse_button_down(button) {
+                input.mouse_down.insert(button);
+            }
+            if is_mouse_button_pressed(button) {
+                input.mouse_pressed.insert(button);
+            }
+            if is_mouse_button_released(button) {
+                input.mouse_released.insert(button);
+            }
+        }
+        input.mouse_position = mouse_position().into();
+        input.mouse_wheel = mouse_wheel().into();
+        input.screen_size = Vec2::new(screen_width(), screen_height());
+        input
+    }
+    /// a key that is held down since this frame.
+    pub fn press_key(&mut self, key: KeyCode) {
+        self.keys_down.insert(key);
+        self.keys_pressed.insert(key);
+    }
+    /// a key that is no longer held down since this frame.
+    pub fn release_key(&mut self, key: KeyCode) {
+        self.keys_down.remove(&key);
+        self.keys_released.insert(key);
+    }
+    pub fn press_mouse_button(&mut self, button: MouseButton) {
+        self.mouse_down.insert(button);
+        self.mouse_pressed.insert(button);
+    }
+    pub fn release_mouse_button(&mut self, button: MouseButton) {
+        self.mouse_down.remove(&button);
+        self.mouse_released.insert(button);
+    }
+    pub fn set_mouse_position(&mut self, x: f32, y: f32) {
+        self.mouse_position = Vec2::new(x, y);
+    }
+    /// the mouse wheel movement of this frame
+    pub fn scroll(&mut self, x: f32, y: f32) {
+        self.mouse_wheel = Vec2::new(x, y);
+    }
+    /// moves a hand filled input to the next frame: keys and buttons stay held down,
+    /// but are no longer pressed/released, and the mouse wheel stops scrolling.
+    pub fn next_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.mouse_pressed.clear();
+        self.mouse_released.clear();
+        self.mouse_wheel = Vec2::ZERO;
+    }
+    pub fn is_key_down(&self, key: KeyCode) -> bool {
+        self.keys_down.contains(&key)
+    }
+    pub fn is_key_pressed(&self, key: KeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+    pub fn is_key_released(&self, key: KeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+    pub fn is_mouse_button_down(&self, button: MouseButton) -> bool {
+        self.mouse_down.contains(&button)
+    }
+    pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_pressed.contains(&button)
+    }
+    pub fn is_mouse_button_released(&self, button: MouseButton) -> bool {
+        self.mouse_released.contains(&button)
+    }
+    pub fn mouse_position(&self) -> (f32, f32) {
+        self.mouse_position.into()
+    }
+    pub fn mouse_wheel(&self) -> (f32, f32) {
+        self.mouse_wheel.into()
+    }
+}
+
+/// the input and delta time of a single recorded frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputFrame {
+    pub delta_time: f32,
+    pub input: InputState,
+}
+
+/// every frame of input of a session, and the seed the session started with.
+/// the seed is `State::seed`, so rngs that are created from it will be reproduced by a replay.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputRecording {
+    pub seed: u64,
+    pub frames: Vec<InputFrame>,
+}
+
+/// the version that is written by `InputRecording::save`
+pub const INPUT_RECORDING_VERSION: u32 = 1;
+const HEADER: &str = "mecslib-input";
+
+impl InputRecording {
+    /// a line based text format. every frame is a line of:
+    /// `dt mouse_x mouse_y wheel_x wheel_y keys_down keys_pressed keys_released mouse_down mouse_pressed mouse_released screen_w screen_h`
+    /// where every set is a comma separated list of names, or `-` if empty.
+    pub fn save(&self) -> String {
+        let mut out = format!("{} {}\nseed {}\n", HEADER, INPUT_RECORDING_VERSION, self.seed);
+        for frame in self.frames.iter() {
+            let input = &frame.input;
+            let _ = writeln!(
+                out,
+                "{} {} {} {} {} {} {} {} {} {} {} {} {}",
+                frame.delta_time,
+                input.mouse_position.x, input.mouse_position.y,
+                input.mouse_wheel.x, input.mouse_wheel.y,
+                join_names(&input.keys_down), join_names(&input.keys_pressed), join_names(&input.keys_released),
+                join_names(&input.mouse_down), join_names(&input.mouse_pressed), join_names(&input.mouse_released),
+                input.screen_size.x, input.screen_size.y,
+            );
+        }
+        out
+    }
+    pub fn load(data: &str) -> Result<Self, Error> {
+        let invalid = |line: usize, msg: &str| Error::new(ErrorKind::InvalidData, format!("line {}: {}", line, msg));
+        let mut lines = data.lines().enumerate();
+        let version = match lines.next().and_then(|(_, l)| l.split_once(' ')) {
+            Some((HEADER, version)) => version.trim().parse::<u32>().map_err(|_| invalid(1, "invalid version"))?,
+            _ => return Err(invalid(1, "missing header")),
+        };
+        if version > INPUT_RECORDING_VERSION {
+            return Err(invalid(1, "unsupported version"));
+        }
+        let seed = match lines.next().and_then(|(_, l)| l.split_once(' ')) {
+            Some(("seed", seed)) => seed.trim().parse::<u64>().map_err(|_| invalid(2, "invalid seed"))?,
+            _ => return Err(invalid(2, "missing seed")),
+        };
+        let mut frames = vec![];
+        for (line_index, line) in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let frame = parse_frame(line).ok_or_else(|| invalid(line_index + 1, "invalid frame"))?;
+            frames.push(frame);
+        }
+        Ok(Self { seed, frames })
+    }
+    pub fn save_to_file(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.save())
+    }
+    pub fn load_from_file(path: &str) -> std::io::Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        Self::load(&data)
+    }
+}
+
+fn join_names<T: std::fmt::Debug>(set: &HashSet<T>) -> String {
+    if set.is_empty() {
+        return "-".to_string();
+    }
+    // sorted so that the same input always produces the same output
+    let mut names: Vec<_> = set.iter().map(|x| format!("{:?}", x)).collect();
+    names.sort();
+    names.join(",")
+}
+
+fn parse_names<T: std::fmt::Debug + Copy + Eq + std::hash::Hash>(data: &str, all: &[T]) -> Option<HashSet<T>> {
+    let mut set = HashSet::new();
+    if data == "-" {
+        return Some(set);
+    }
+    for name in data.split(',') {
+        set.insert(find_by_name(name, all)?);
+    }
+    Some(set)
+}
+
+fn find_by_name<T: std::fmt::Debug + Copy>(name: &str, all: &[T]) -> Option<T> {
+    all.iter().find(|x| format!("{:?}", x) == name).copied()
+}
+
+/// the key with this name, where the name is the name of the KeyCode variant, eg: "Left", "Key1", "A"
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    find_by_name(name, &ALL_KEY_CODES)
+}
+
+/// the mouse button with this name, where the name is the name of the MouseButton variant, eg: "Left"
+pub fn mouse_button_from_name(name: &str) -> Option<MouseButton> {
+    find_by_name(name, &ALL_MOUSE_BUTTONS)
+}
+
+fn parse_frame(line: &str) -> Option<InputFrame> {
+    let parts: Vec<_> = line.split_whitespace().collect();
+    if parts.len() != 13 {
+        return None;
+    }
+    let float = |i: usize| parts[i].parse::<f32>().ok();
+    let input = InputState {
+        screen_size: Vec2::new(float(11)?, float(12)?),
+        mouse_position: Vec2::new(float(1)?, float(2)?),
+        mouse_wheel: Vec2::new(float(3)?, float(4)?),
+        keys_down: parse_names(parts[5], &ALL_KEY_CODES)?,
+        keys_pressed: parse_names(parts[6], &ALL_KEY_CODES)?,
+        keys_released: parse_names(parts[7], &ALL_KEY_CODES)?,
+        mouse_down: parse_names(parts[8], &ALL_MOUSE_BUTTONS)?,
+        mouse_pressed: parse_names(parts[9], &ALL_MOUSE_BUTTONS)?,
+        mouse_released: parse_names(parts[10], &ALL_MOUSE_BUTTONS)?,
+    };
+    Some(InputFrame { delta_time: float(0)?, input })
+}
+
+/// decides where the input of every frame comes from. stored in `State::recorder`,
+/// see: `State::start_recording`, `State::start_replay`
+#[derive(Default)]
+pub enum InputRecorder {
+    /// the live input is used as is
+    #[default]
+    Off,
+    /// the live input is used, and every frame is added to the recording
+    Recording(InputRecording),
+    /// the live input is ignored, and the recorded frames are used instead.
+    /// once every frame was replayed, the recorder switches back to `Off`.
+    Replaying { recording: InputRecording, frame: usize },
+}
+
+impl InputRecorder {
+    /// given the live delta time and input of the current frame,
+    /// returns the delta time and input that the frame should use.
+    pub fn next_frame(&mut self, delta_time: f32, live: InputState) -> (f32, InputState) {
+        match self {
+            InputRecorder::Off => (delta_time, live),
+            InputRecorder::Recording(recording) => {
+                recording.frames.push(InputFrame { delta_time, input: live.clone() });
+                (delta_time, live)
+            }
+            InputRecorder::Replaying { recording, frame } => {
+                if let Some(recorded) = recording.frames.get(*frame) {
+                    *frame += 1;
+                    return (recorded.delta_time, recorded.input.clone());
+                }
+                *self = InputRecorder::Off;
+                (delta_time, live)
+            }
+        }
+    }
+    pub fn is_recording(&self) -> bool {
+        matches!(self, InputRecorder::Recording(_))
+    }
+    pub fn is_replaying(&self) -> bool {
+        matches!(self, InputRecorder::Replaying { .. })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> InputRecording {
+        let mut input = InputState::default();
+        input.set_mouse_position(12.5, -3.0);
+        input.scroll(0.0, 1.0);
+        input.press_key(KeyCode::A);
+        input.press_key(KeyCode::Left);
+        input.press_mouse_button(MouseButton::Right);
+        let first = InputFrame { delta_time: 0.016, input: input.clone() };
+        input.next_frame();
+        input.release_key(KeyCode::A);
+        InputRecording { seed: 42, frames: vec![first, InputFrame { delta_time: 0.02, input }] }
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let recording = recording();
+        assert_eq!(InputRecording::load(&recording.save()).unwrap(), recording);
+    }
+
+    #[test]
+    fn bad_header_is_invalid() {
+        for data in ["", "mecslib-world 1\nseed 1\n", "mecslib-input x\nseed 1\n", "mecslib-input 1\nnope\n"] {
+            assert_eq!(InputRecording::load(data).unwrap_err().kind(), ErrorKind::InvalidData, "{}", data);
+        }
+    }
+
+    #[test]
+    fn truncated_frame_is_invalid() {
+        let data = recording().save();
+        let truncated = &data[..data.trim_end().rfind(' ').unwrap()];
+        let err = InputRecording::load(truncated).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 4:"), "{}", err);
+    }
+
+    #[test]
+    fn newer_version_is_unsupported() {
+        let data = recording().save().replacen(
+            &format!("{} {}", HEADER, INPUT_RECORDING_VERSION),
+            &format!("{} {}", HEADER, INPUT_RECORDING_VERSION + 1),
+            1,
+        );
+        let err = InputRecording::load(&data).unwrap_err();
+        assert!(err.to_string().contains("unsupported version"), "{}", err);
+    }
+}
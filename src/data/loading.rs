@@ -39,11 +39,31 @@ macro_rules! create_texture_enum {
                 )*
                 map
             }
+            fn name(&self) -> &'static str {
+                match self {
+                    $(
+                        Self::$x => stringify!($x),
+                    )*
+                }
+            }
+            fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $(
+                        stringify!($x) => Some(Self::$x),
+                    )*
+                    _ => None,
+                }
+            }
         }
     };
 }
 
-pub trait TextureEnum: Eq + PartialEq + Hash {
+pub trait TextureEnum: Eq + PartialEq + Hash + 'static {
     fn load() -> HashMap<Self, Texture2D>
         where Self: Sized;
+    /// the name of the variant. used to refer to a texture
+    /// without storing its pixel data, eg: when saving the world.
+    fn name(&self) -> &'static str;
+    fn from_name(name: &str) -> Option<Self>
+        where Self: Sized;
 }
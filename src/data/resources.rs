@@ -0,0 +1,43 @@
+//! Resources are singletons stored on the `State` by their type, instead of as
+//! fields of the user state. This lets systems depend on the resources they need
+//! without knowing the user's state type. Example:
+//! ```
+//! s.insert_resource(VoronoiTiling::default());
+//! let tiling = s.resource_mut::<VoronoiTiling>();
+//! ```
+
+use std::{any::{Any, TypeId}, collections::HashMap};
+
+/// a map of at most one value per type.
+#[derive(Default)]
+pub struct Resources {
+    map: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Resources {
+    /// inserts the resource, returning the previous resource of the same type, if any.
+    pub fn insert<R: 'static>(&mut self, resource: R) -> Option<R> {
+        let previous = self.map.insert(TypeId::of::<R>(), Box::new(resource))?;
+        previous.downcast().ok().map(|r| *r)
+    }
+    pub fn remove<R: 'static>(&mut self) -> Option<R> {
+        let resource = self.map.remove(&TypeId::of::<R>())?;
+        resource.downcast().ok().map(|r| *r)
+    }
+    pub fn contains<R: 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<R>())
+    }
+    pub fn get<R: 'static>(&self) -> Option<&R> {
+        self.map.get(&TypeId::of::<R>())?.downcast_ref()
+    }
+    pub fn get_mut<R: 'static>(&mut self) -> Option<&mut R> {
+        self.map.get_mut(&TypeId::of::<R>())?.downcast_mut()
+    }
+    /// returns the resource of this type, inserting the default value if it doesn't exist yet.
+    pub fn get_or_default<R: Default + 'static>(&mut self) -> &mut R {
+        self.map.entry(TypeId::of::<R>())
+            .or_insert_with(|| Box::<R>::default())
+            .downcast_mut()
+            .expect("resource is keyed by its own type id")
+    }
+}
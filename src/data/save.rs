@@ -0,0 +1,703 @@
+//! Saving and loading the entities of `State::world` to a versioned, line based text format.
+//! Every saved entity starts with an `entity` line, followed by one line per component:
+//! ```text
+//! mecslib-world 1
+//! entity
+//! Transform 1 0 0 1 10 20
+//! Drawable texture test 0
+//! Layer1
+//! entity
+//! Transform 1 0 0 1 0 0
+//! Parent 0 1 0 0 1 5 0
+//! ```
+//! Parent components refer to other entities by the order they were saved in.
+//! Drawable textures are saved as the name of their `TextureEnum` variant, or
+//! as embedded pixel data if the texture was generated at runtime.
+//! Animations are saved with their current frame, and start that frame over when loaded.
+//! Colors are saved as `r,g,b,a`, or `-` if there is no color.
+//! Text drawables are saved with the default font, as fonts are loaded at runtime.
+//! Components of your own can be saved by implementing `SaveComponent`, and registering
+//! them with `WorldFormat::register`.
+
+use std::{collections::HashMap, fmt::Write};
+
+use hecs::*;
+use macroquad::prelude::*;
+
+use crate::{
+    components::*,
+    data::{
+        world::*,
+        loading::*,
+    },
+};
+
+/// the version that is written by `WorldFormat::save`.
+/// increment whenever the format of a builtin component changes.
+pub const WORLD_FORMAT_VERSION: u32 = 1;
+const HEADER: &str = "mecslib-world";
+
+/// a component that can be saved to a single line of text
+pub trait SaveComponent: Component + Sized {
+    /// the name the component is saved under. must be unique, and not contain whitespace.
+    const NAME: &'static str;
+    /// the data of the component. must not contain newlines.
+    fn save(&self) -> String;
+    /// returns None if the data is invalid.
+    fn load(data: &str) -> Option<Self>;
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    /// the data does not start with the `mecslib-world <version>` header
+    MissingHeader,
+    /// the data was saved by a newer version of the format than this library supports
+    UnsupportedVersion(u32),
+    /// a component appeared before the first `entity` line
+    ComponentWithoutEntity { line: usize },
+    /// a component that was not registered with `WorldFormat::register`
+    UnknownComponent { line: usize, name: String },
+    InvalidData { line: usize, name: String },
+}
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::MissingHeader => write!(f, "missing '{}' header", HEADER),
+            LoadError::UnsupportedVersion(v) => write!(f, "unsupported version {}, expected at most {}", v, WORLD_FORMAT_VERSION),
+            LoadError::ComponentWithoutEntity { line } => write!(f, "line {}: component before the first entity", line),
+            LoadError::UnknownComponent { line, name } => write!(f, "line {}: unknown component {}", line, name),
+            LoadError::InvalidData { line, name } => write!(f, "line {}: invalid data for component {}", line, name),
+        }
+    }
+}
+impl std::error::Error for LoadError {}
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+struct ComponentFormat {
+    name: &'static str,
+    has: fn(&EntityRef) -> bool,
+    save: fn(&EntityRef) -> Option<String>,
+    load: fn(&mut World, Entity, &str) -> bool,
+}
+
+fn has_component<C: SaveComponent>(entity: &EntityRef) -> bool {
+    entity.has::<C>()
+}
+
+fn save_component<C: SaveComponent>(entity: &EntityRef) -> Option<String> {
+    entity.get::<&C>().map(|c| c.save())
+}
+
+fn load_component<C: SaveComponent>(world: &mut World, entity: Entity, data: &str) -> bool {
+    match C::load(data) {
+        Some(c) => world.insert_one(entity, c).is_ok(),
+        None => false,
+    }
+}
+
+/// the set of components that are saved and loaded.
+/// by default this is every builtin component, eg:
+/// ```
+/// let format = WorldFormat::new().with::<MyComponent>();
+/// format.save_to_file(&s, "map.world")?;
+/// s.world.clear();
+/// format.load_from_file(&mut s, "map.world")?;
+/// ```
+pub struct WorldFormat {
+    components: Vec<ComponentFormat>,
+}
+impl Default for WorldFormat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WorldFormat {
+    /// a format with all builtin components registered. `Parent` and `Drawable`
+    /// are always handled, as they refer to other entities and to textures.
+    pub fn new() -> Self {
+        Self { components: vec![] }
+            .with::<Transform>()
+            .with::<Tint>()
+            .with::<Triangle>()
+            .with::<LineSegment>()
+            .with::<Hidden>()
+            .with::<Layer0>()
+            .with::<Layer1>()
+            .with::<Layer2>()
+            .with::<Layer3>()
+            .with::<Layer4>()
+            .with::<Layer5>()
+            .with::<Layer6>()
+            .with::<Layer7>()
+            .with::<Layer8>()
+            .with::<Layer9>()
+    }
+    /// save and load the component. if a component with the same name
+    /// was already registered, it is replaced.
+    pub fn register<C: SaveComponent>(&mut self) {
+        self.components.retain(|c| c.name != C::NAME);
+        self.components.push(ComponentFormat {
+            name: C::NAME,
+            has: has_component::<C>,
+            save: save_component::<C>,
+            load: load_component::<C>,
+        });
+    }
+    pub fn with<C: SaveComponent>(mut self) -> Self {
+        self.register::<C>();
+        self
+    }
+
+    /// saves every entity that has at least one component known to this format.
+    /// must be called with a graphics context if any entity has a generated texture,
+    /// as its pixel data is read from the GPU.
+    pub fn save<U: UserState<T>, T: TextureEnum>(&self, s: &State<U, T>) -> String {
+        // first find which entities will be saved, so parents can
+        // refer to entities that are saved after their children
+        let mut indices = HashMap::new();
+        for entity in s.world.iter() {
+            let has_saved_component = entity.has::<Parent>() || entity.has::<Drawable>() ||
+                self.components.iter().any(|c| (c.has)(&entity));
+            if has_saved_component {
+                indices.insert(entity.entity(), indices.len());
+            }
+        }
+        let mut saved: Vec<_> = s.world.iter().filter(|e| indices.contains_key(&e.entity())).collect();
+        saved.sort_by_key(|e| indices[&e.entity()]);
+
+        let mut out = format!("{} {}\n", HEADER, WORLD_FORMAT_VERSION);
+        for entity in saved {
+            out.push_str("entity\n");
+            for c in self.components.iter() {
+                if let Some(data) = (c.save)(&entity) {
+                    push_line(&mut out, c.name, &data);
+                }
+            }
+            if let Some(parent) = entity.get::<&Parent>() {
+                // a parent that wasnt saved would be dangling, so leave it out
+                if let Some(index) = indices.get(&parent.parent) {
+                    let data = format!("{} {}", index, parent.local_transform.save());
+                    push_line(&mut out, "Parent", &data);
+                }
+            }
+            if let Some(drawable) = entity.get::<&Drawable>() {
+                push_line(&mut out, "Drawable", &save_drawable(s, &drawable));
+            }
+        }
+        out
+    }
+
+    /// spawns every entity from the data into the world, in addition to the entities already in it.
+    /// returns the spawned entities in the order they were saved.
+    /// if the data is invalid, the entities spawned so far are despawned again.
+    /// must be called with a graphics context if the data contains generated textures.
+    pub fn load<U: UserState<T>, T: TextureEnum>(&self, s: &mut State<U, T>, data: &str) -> Result<Vec<Entity>, LoadError> {
+        let mut lines = data.lines().enumerate();
+        let (_, header) = lines.next().ok_or(LoadError::MissingHeader)?;
+        let version = match header.split_once(' ') {
+            Some((HEADER, version)) => version.trim().parse::<u32>().map_err(|_| LoadError::MissingHeader)?,
+            _ => return Err(LoadError::MissingHeader),
+        };
+        if version > WORLD_FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+
+        let mut spawned: Vec<Entity> = vec![];
+        if let Err(e) = self.load_entities(s, lines, &mut spawned) {
+            for entity in spawned {
+                let _ = s.world.despawn(entity);
+            }
+            return Err(e);
+        }
+        Ok(spawned)
+    }
+
+    fn load_entities<'a, U: UserState<T>, T: TextureEnum>(
+        &self,
+        s: &mut State<U, T>,
+        lines: impl Iterator<Item = (usize, &'a str)>,
+        spawned: &mut Vec<Entity>,
+    ) -> Result<(), LoadError> {
+        // parents are inserted after all entities exist, as they can refer to entities that come later
+        let mut parents = vec![];
+        for (line_index, line) in lines {
+            let line_number = line_index + 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line == "entity" {
+                spawned.push(s.world.spawn(()));
+                continue;
+            }
+            let entity = *spawned.last().ok_or(LoadError::ComponentWithoutEntity { line: line_number })?;
+            let (name, data) = line.split_once(' ').unwrap_or((line, ""));
+            let invalid = || LoadError::InvalidData { line: line_number, name: name.to_string() };
+            match name {
+                "Parent" => {
+                    let (index, transform) = data.split_once(' ').ok_or_else(invalid)?;
+                    let index = index.parse::<usize>().map_err(|_| invalid())?;
+                    let local_transform = Transform::load(transform).ok_or_else(invalid)?;
+                    parents.push((entity, index, local_transform, line_number));
+                }
+                "Drawable" => {
+                    let drawable = load_drawable(s, data).ok_or_else(invalid)?;
+                    let _ = s.world.insert_one(entity, drawable);
+                }
+                _ => {
+                    let c = self.components.iter().find(|c| c.name == name)
+                        .ok_or_else(|| LoadError::UnknownComponent { line: line_number, name: name.to_string() })?;
+                    if !(c.load)(&mut s.world, entity, data) {
+                        return Err(invalid());
+                    }
+                }
+            }
+        }
+        for (entity, index, local_transform, line) in parents {
+            let parent = *spawned.get(index).ok_or(LoadError::InvalidData { line, name: "Parent".to_string() })?;
+            let _ = s.world.insert_one(entity, Parent { parent, local_transform });
+        }
+        Ok(())
+    }
+
+    pub fn save_to_file<U: UserState<T>, T: TextureEnum>(&self, s: &State<U, T>, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.save(s))
+    }
+    pub fn load_from_file<U: UserState<T>, T: TextureEnum>(&self, s: &mut State<U, T>, path: &str) -> Result<Vec<Entity>, LoadError> {
+        let data = std::fs::read_to_string(path)?;
+        self.load(s, &data)
+    }
+}
+
+fn push_line(out: &mut String, name: &str, data: &str) {
+    if data.is_empty() {
+        out.push_str(name);
+    } else {
+        let _ = write!(out, "{} {}", name, data);
+    }
+    out.push('\n');
+}
+
+/// parses exactly N whitespace separated floats
+fn parse_floats<const N: usize>(data: &str) -> Option<[f32; N]> {
+    let mut out = [0.0; N];
+    let mut parts = data.split_whitespace();
+    for value in out.iter_mut() {
+        *value = parts.next()?.parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(out)
+}
+
+fn save_drawable<U: UserState<T>, T: TextureEnum>(s: &State<U, T>, drawable: &Drawable) -> String {
+    match drawable {
+        Drawable::Texture { d, dont_center } => {
+            let dont_center = *dont_center as u8;
+            if let Some((key, _)) = s.textures.iter().find(|(_, t)| *t == d) {
+                return format!("texture {} {}", key.name(), dont_center);
+            }
+            // generated at runtime, so embed the pixels as hex
+            let image = d.get_texture_data();
+            let mut hex = String::with_capacity(image.bytes.len() * 2);
+            for b in image.bytes.iter() {
+                let _ = write!(hex, "{:02x}", b);
+            }
+            format!("pixels {} {} {} {}", image.width, image.height, dont_center, hex)
+        }
+        Drawable::Animation { d, dont_center } => {
+            // sheets generated at runtime are not saved, and load as an empty texture
+            let key = s.textures.iter().find(|(_, t)| **t == d.sheet).map(|(k, _)| k.name()).unwrap_or("-");
+            let mut out = format!("animation {} {} {:?} {} {}", key, *dont_center as u8, d.mode, d.frame(), d.frames.len());
+            for r in d.frames.iter() {
+                let _ = write!(out, " {} {} {} {}", r.x, r.y, r.w, r.h);
+            }
+            let _ = write!(out, " {}", d.durations.len());
+            for duration in d.durations.iter() {
+                let _ = write!(out, " {}", duration);
+            }
+            out
+        }
+        Drawable::Circle { radius, paint } => format!("circle {} {}", radius, save_paint(paint)),
+        Drawable::Rect { size, paint } => format!("rect {} {} {}", size.x, size.y, save_paint(paint)),
+        Drawable::Polygon { points, paint } => format!("polygon {} {}", save_paint(paint), save_points(points)),
+        Drawable::Polyline { points, color, width } => {
+            format!("polyline {} {} {}", save_color(Some(*color)), width, save_points(points))
+        }
+        // the font is a runtime handle, so text is loaded with the default font
+        Drawable::Text { text, font: _, size, align, color, outline, outline_width, space } => {
+            format!(
+                "text {:?} {:?} {} {} {} {} {}",
+                space, align, size, save_color(Some(*color)), save_color(*outline), outline_width, escape_text(text),
+            )
+        }
+    }
+}
+
+fn save_color(color: Option<Color>) -> String {
+    match color {
+        Some(c) => format!("{},{},{},{}", c.r, c.g, c.b, c.a),
+        None => "-".to_string(),
+    }
+}
+
+fn load_color(data: &str) -> Option<Option<Color>> {
+    if data == "-" {
+        return Some(None);
+    }
+    let [r, g, b, a] = parse_floats::<4>(&data.replace(',', " "))?;
+    Some(Some(Color::new(r, g, b, a)))
+}
+
+/// `<fill> <stroke> <stroke_width>`
+fn save_paint(paint: &Paint) -> String {
+    format!("{} {} {}", save_color(paint.fill), save_color(paint.stroke), paint.stroke_width)
+}
+
+fn load_paint<'a>(parts: &mut impl Iterator<Item = &'a str>) -> Option<Paint> {
+    Some(Paint {
+        fill: load_color(parts.next()?)?,
+        stroke: load_color(parts.next()?)?,
+        stroke_width: parts.next()?.parse().ok()?,
+    })
+}
+
+/// `<count> x y x y ...`
+fn save_points(points: &[Vec2]) -> String {
+    let mut out = points.len().to_string();
+    for p in points {
+        let _ = write!(out, " {} {}", p.x, p.y);
+    }
+    out
+}
+
+fn load_points<'a>(parts: &mut impl Iterator<Item = &'a str>) -> Option<Vec<Vec2>> {
+    let count: usize = parts.next()?.parse().ok()?;
+    let mut points = vec![];
+    for _ in 0..count {
+        let x = parts.next()?.parse().ok()?;
+        let y = parts.next()?.parse().ok()?;
+        points.push(Vec2::new(x, y));
+    }
+    Some(points)
+}
+
+/// `text <space> <align> <size> <color> <outline> <outline_width> <text>`.
+/// the text is the rest of the line, so that its whitespace is kept, see: `escape_text`
+fn load_text(data: &str) -> Option<Drawable> {
+    let mut parts = data.splitn(8, ' ');
+    parts.next()?;
+    let space = match parts.next()? {
+        "World" => TextSpace::World,
+        "Screen" => TextSpace::Screen,
+        _ => return None,
+    };
+    let align = match parts.next()? {
+        "Left" => TextAlign::Left,
+        "Center" => TextAlign::Center,
+        "Right" => TextAlign::Right,
+        _ => return None,
+    };
+    let size = parts.next()?.parse().ok()?;
+    let color = load_color(parts.next()?)??;
+    let outline = load_color(parts.next()?)?;
+    let outline_width = parts.next()?.parse().ok()?;
+    let text = unescape_text(parts.next()?)?;
+    Some(Drawable::Text { text, font: None, size, align, color, outline, outline_width, space })
+}
+
+/// escapes backslashes and every control character, eg: `\n`, `\r`, `\u{1b}`,
+/// so that the text stays on a single line and no character is lost when the lines are split
+fn escape_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{{{:x}}}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape_text(data: &str) -> Option<String> {
+    let mut text = String::new();
+    let mut chars = data.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => text.push('\n'),
+            'r' => text.push('\r'),
+            't' => text.push('\t'),
+            '\\' => text.push('\\'),
+            'u' => {
+                if chars.next()? != '{' {
+                    return None;
+                }
+                let hex: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                text.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+            }
+            _ => return None,
+        }
+    }
+    Some(text)
+}
+
+fn load_drawable<U: UserState<T>, T: TextureEnum>(s: &State<U, T>, data: &str) -> Option<Drawable> {
+    if data.starts_with("text ") {
+        return load_text(data);
+    }
+    let mut parts = data.split_whitespace();
+    match parts.next()? {
+        "texture" => {
+            let key = T::from_name(parts.next()?)?;
+            let dont_center = parts.next()? == "1";
+            // same as Drawable::texture, an empty texture is used if it wasnt loaded
+            let d = s.textures.get(&key).copied().unwrap_or_else(Texture2D::empty);
+            Some(Drawable::Texture { d, dont_center })
+        }
+        "pixels" => {
+            let width: u16 = parts.next()?.parse().ok()?;
+            let height: u16 = parts.next()?.parse().ok()?;
+            let dont_center = parts.next()? == "1";
+            let hex = parts.next()?.as_bytes();
+            if hex.len() != width as usize * height as usize * 8 {
+                return None;
+            }
+            let mut bytes = Vec::with_capacity(hex.len() / 2);
+            for pair in hex.chunks(2) {
+                let pair = std::str::from_utf8(pair).ok()?;
+                bytes.push(u8::from_str_radix(pair, 16).ok()?);
+            }
+            let d = Texture2D::from_rgba8(width, height, &bytes);
+            // needed to prevent pixelart blur
+            d.set_filter(FilterMode::Nearest);
+            Some(Drawable::Texture { d, dont_center })
+        }
+        "animation" => {
+            let key = parts.next()?;
+            let sheet = match T::from_name(key) {
+                Some(key) => s.textures.get(&key).copied().unwrap_or_else(Texture2D::empty),
+                None if key == "-" => Texture2D::empty(),
+                None => return None,
+            };
+            let dont_center = parts.next()? == "1";
+            let mode = match parts.next()? {
+                "Loop" => AnimationMode::Loop,
+                "PingPong" => AnimationMode::PingPong,
+                "Once" => AnimationMode::Once,
+                _ => return None,
+            };
+            let frame: usize = parts.next()?.parse().ok()?;
+            let count: usize = parts.next()?.parse().ok()?;
+            let mut frames = vec![];
+            for _ in 0..count {
+                let [x, y, w, h] = [parts.next()?, parts.next()?, parts.next()?, parts.next()?].map(|p| p.parse().ok());
+                frames.push(Rect::new(x?, y?, w?, h?));
+            }
+            let count: usize = parts.next()?.parse().ok()?;
+            let mut durations = vec![];
+            for _ in 0..count {
+                durations.push(parts.next()?.parse().ok()?);
+            }
+            let mut d = SpriteAnimation::from_texture(sheet, frames, 0.0).with_durations(durations).with_mode(mode);
+            d.set_frame(frame);
+            Some(Drawable::Animation { d, dont_center })
+        }
+        "circle" => {
+            let radius = parts.next()?.parse().ok()?;
+            Some(Drawable::Circle { radius, paint: load_paint(&mut parts)? })
+        }
+        "rect" => {
+            let size = Vec2::new(parts.next()?.parse().ok()?, parts.next()?.parse().ok()?);
+            Some(Drawable::Rect { size, paint: load_paint(&mut parts)? })
+        }
+        "polygon" => {
+            let paint = load_paint(&mut parts)?;
+            Some(Drawable::Polygon { points: load_points(&mut parts)?, paint })
+        }
+        "polyline" => {
+            let color = load_color(parts.next()?)??;
+            let width = parts.next()?.parse().ok()?;
+            Some(Drawable::Polyline { points: load_points(&mut parts)?, color, width })
+        }
+        _ => None,
+    }
+}
+
+impl SaveComponent for Transform {
+    const NAME: &'static str = "Transform";
+    fn save(&self) -> String {
+        let [a, b, c, d, e, f] = self.d.to_cols_array();
+        format!("{} {} {} {} {} {}", a, b, c, d, e, f)
+    }
+    fn load(data: &str) -> Option<Self> {
+        let cols = parse_floats::<6>(data)?;
+        Some(Self { d: Affine2::from_cols_array(&cols) })
+    }
+}
+
+impl SaveComponent for Tint {
+    const NAME: &'static str = "Tint";
+    fn save(&self) -> String {
+        format!("{} {} {} {}", self.d.r, self.d.g, self.d.b, self.d.a)
+    }
+    fn load(data: &str) -> Option<Self> {
+        let [r, g, b, a] = parse_floats::<4>(data)?;
+        Some(Self { d: Color::new(r, g, b, a) })
+    }
+}
+
+impl SaveComponent for Triangle {
+    const NAME: &'static str = "Triangle";
+    fn save(&self) -> String {
+        format!(
+            "{} {} {} {} {} {} {}",
+            self.a.x, self.a.y, self.b.x, self.b.y, self.c.x, self.c.y, save_paint(&self.paint),
+        )
+    }
+    fn load(data: &str) -> Option<Self> {
+        let mut parts = data.split_whitespace();
+        let mut points = [0.0; 6];
+        for value in points.iter_mut() {
+            *value = parts.next()?.parse().ok()?;
+        }
+        let [ax, ay, bx, by, cx, cy] = points;
+        let paint = load_paint(&mut parts)?;
+        Some(Self::new(Vec2::new(ax, ay), Vec2::new(bx, by), Vec2::new(cx, cy), paint))
+    }
+}
+
+impl SaveComponent for LineSegment {
+    const NAME: &'static str = "LineSegment";
+    fn save(&self) -> String {
+        format!("{} {} {} {} {} {}", self.a.x, self.a.y, self.b.x, self.b.y, self.width, save_color(Some(self.color)))
+    }
+    fn load(data: &str) -> Option<Self> {
+        let (floats, color) = data.rsplit_once(' ')?;
+        let color = load_color(color)??;
+        let [ax, ay, bx, by, width] = parse_floats::<5>(floats)?;
+        Some(Self::new(Vec2::new(ax, ay), Vec2::new(bx, by), width).with_color(color))
+    }
+}
+
+/// unit struct components have no data, so they are saved as just their name
+macro_rules! impl_save_unit_component {
+    ($($name:ident),*) => {
+        $(
+            impl SaveComponent for $name {
+                const NAME: &'static str = stringify!($name);
+                fn save(&self) -> String {
+                    String::new()
+                }
+                fn load(_data: &str) -> Option<Self> {
+                    Some($name)
+                }
+            }
+        )*
+    };
+}
+
+impl_save_unit_component!(Hidden, Layer0, Layer1, Layer2, Layer3, Layer4, Layer5, Layer6, Layer7, Layer8, Layer9);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    fn load(data: &str) -> (TestState, Result<Vec<Entity>, LoadError>) {
+        let mut s = TestState::empty();
+        let loaded = WorldFormat::new().load(&mut s, data);
+        (s, loaded)
+    }
+
+    #[test]
+    fn round_trip_keeps_components_and_parents() {
+        let mut s = TestState::empty();
+        let parent = s.world.spawn((
+            Transform::from_scale_angle_position(2.0, 0.5, (10.0, 20.0)),
+            Tint { d: RED },
+            Layer1,
+        ));
+        let triangle = Triangle::new(Vec2::ZERO, Vec2::X, Vec2::Y, Paint::fill(BLUE).with_stroke(GREEN, 2.0));
+        let child = s.world.spawn((Transform::default(), triangle, Layer2));
+        s.world.spawn((
+            Transform::default(),
+            LineSegment::new(Vec2::ZERO, Vec2::new(3.0, 4.0), 1.5).with_color(YELLOW),
+            Drawable::text("a  b\\c\nd\r\t\u{1b} ", 12.0),
+        ));
+        crate::system::hierarchy::set_parent(&mut s.world, child, parent).unwrap();
+
+        let saved = WorldFormat::new().save(&s);
+        let (loaded, entities) = load(&saved);
+        let entities = entities.unwrap();
+        assert_eq!(entities.len(), 3);
+        assert_eq!(loaded.world.len(), 3);
+
+        let (loaded_child, parent_component) = loaded.world.query::<&Parent>().iter()
+            .map(|(e, p)| (e, p.parent)).next().unwrap();
+        assert_eq!(loaded.world.get::<&Tint>(parent_component).unwrap().d, RED);
+        assert!(loaded.world.get::<&Layer1>(parent_component).is_ok());
+        let loaded_triangle = loaded.world.get::<&Triangle>(loaded_child).unwrap();
+        assert_eq!(loaded_triangle.paint, Paint::fill(BLUE).with_stroke(GREEN, 2.0));
+        assert_eq!(loaded_triangle.b, Vec2::X);
+
+        let mut lines = loaded.world.query::<(&LineSegment, &Drawable)>();
+        let (_, (line, drawable)) = lines.iter().next().unwrap();
+        assert_eq!(line.color, YELLOW);
+        assert_eq!(line.width, 1.5);
+        match drawable {
+            Drawable::Text { text, size, .. } => {
+                assert_eq!(text, "a  b\\c\nd\r\t\u{1b} ");
+                assert_eq!(*size, 12.0);
+            }
+            _ => panic!("expected text, got {:?}", drawable),
+        }
+    }
+
+    #[test]
+    fn header_errors() {
+        assert!(matches!(load("").1, Err(LoadError::MissingHeader)));
+        assert!(matches!(load("something 1\n").1, Err(LoadError::MissingHeader)));
+        assert!(matches!(load("mecslib-world 99\n").1, Err(LoadError::UnsupportedVersion(99))));
+        assert!(matches!(load("mecslib-world 1\nTint 1 1 1 1\n").1, Err(LoadError::ComponentWithoutEntity { line: 2 })));
+    }
+
+    #[test]
+    fn errors_despawn_the_partially_loaded_entities() {
+        let (s, loaded) = load("mecslib-world 1\nentity\nTint 1 1 1 1\nentity\nUnknown 1\n");
+        assert!(matches!(loaded, Err(LoadError::UnknownComponent { line: 5, .. })));
+        assert_eq!(s.world.len(), 0);
+
+        let (s, loaded) = load("mecslib-world 1\nentity\nTransform 1 0 0 1 0 0\nParent 7 1 0 0 1 0 0\n");
+        assert!(matches!(loaded, Err(LoadError::InvalidData { line: 4, .. })));
+        assert_eq!(s.world.len(), 0);
+
+        let (s, loaded) = load("mecslib-world 1\nentity\nTint 1 1 nope 1\n");
+        assert!(matches!(loaded, Err(LoadError::InvalidData { line: 3, .. })));
+        assert_eq!(s.world.len(), 0);
+    }
+
+    #[test]
+    fn huge_counts_are_invalid_data() {
+        let (_, loaded) = load(&format!("mecslib-world 1\nentity\nDrawable polygon - - 0 {}\n", usize::MAX));
+        assert!(matches!(loaded, Err(LoadError::InvalidData { line: 3, .. })));
+        let (_, loaded) = load(&format!("mecslib-world 1\nentity\nDrawable animation - 0 Loop 0 {} 0 0 1 1\n", usize::MAX));
+        assert!(matches!(loaded, Err(LoadError::InvalidData { line: 3, .. })));
+    }
+}
@@ -0,0 +1,81 @@
+//! Time controls for the simulation: pausing, single stepping, and scaling
+//! the speed of the simulation. The render stage is not affected by any of these,
+//! so the game keeps drawing while the simulation is paused or slowed down.
+
+/// stored in `State::time`. Example:
+/// ```
+/// s.time.scale = 0.25; // quarter speed
+/// s.time.pause();
+/// s.time.step(); // advance a single simulation step while paused
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Time {
+    /// multiplier applied to the delta time that the simulation systems receive.
+    /// with a fixed timestep, the step size stays the same,
+    /// but steps happen more or less often.
+    pub scale: f32,
+    pub paused: bool,
+    /// how many simulation steps to run while paused
+    steps_requested: usize,
+    /// simulation time that has passed in seconds, affected by pausing and scaling
+    pub elapsed: f64,
+    /// real time that has passed in seconds, not affected by pausing and scaling
+    pub real_elapsed: f64,
+    /// the real delta time of the current frame
+    pub frame_delta: f32,
+    /// number of frames rendered so far
+    pub frame: u64,
+}
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            paused: false,
+            steps_requested: 0,
+            elapsed: 0.0,
+            real_elapsed: 0.0,
+            frame_delta: 0.0,
+            frame: 0,
+        }
+    }
+}
+
+impl Time {
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.steps_requested = 0;
+    }
+    pub fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+    /// while paused, runs a single simulation step on the next frame.
+    /// has no effect if not paused.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.steps_requested += 1;
+        }
+    }
+    /// called by the schedule at the start of every frame with the real frame time.
+    /// returns the delta time for the simulation, which is 0.0 while paused.
+    pub fn begin_frame(&mut self, frame_delta: f32) -> f32 {
+        self.frame_delta = frame_delta;
+        self.real_elapsed += frame_delta as f64;
+        self.frame += 1;
+        if self.paused {
+            0.0
+        } else {
+            frame_delta * self.scale
+        }
+    }
+    /// returns the number of steps requested while paused, and resets it.
+    pub fn take_requested_steps(&mut self) -> usize {
+        std::mem::take(&mut self.steps_requested)
+    }
+}
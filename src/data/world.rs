@@ -4,15 +4,25 @@ use hecs::*;
 use macroquad::prelude::*;
 
 use crate::{
-    data::loading::*,
+    data::{
+        loading::*,
+        events::*,
+        resources::*,
+        app_state::*,
+        time::*,
+        input::*,
+        actions::*,
+    },
     system::{
-        stateless::*,
+        schedule::*,
         stateful::*
     },
 };
 
-pub trait UserState<T: TextureEnum>: Default {
-    fn initialize(s: &mut State<Self, T>);
+pub trait UserState<T: TextureEnum>: Default + 'static {
+    /// called once when the state is created. setup work that needs
+    /// to run inside the game loop can instead be done in a `Stage::Startup` system.
+    fn initialize(_s: &mut State<Self, T>) {}
 }
 
 /// state that is managed by the application
@@ -24,6 +34,24 @@ pub struct State<U: UserState<T>, T: TextureEnum> {
     pub usr: U,
     pub clear_color: Color,
     pub coords: CoordTransform,
+    /// only used when the schedule has a fixed timestep, see: `Schedule::with_fixed_timestep`
+    pub fixed: FixedTimestep,
+    /// typed events that systems can send to each other
+    pub events: EventQueues,
+    /// singletons keyed by their type. see: `insert_resource`
+    pub resources: Resources,
+    /// pausing, stepping and scaling of the simulation
+    pub time: Time,
+    /// the keyboard and mouse input of the current frame. filled by `run`, or by hand when
+    /// running headless. systems should read input from here rather than from macroquad directly.
+    pub input: InputState,
+    /// the bindings of named actions, see: `action_down`
+    pub actions: ActionMap,
+    /// records or replays the input of every frame. see: `start_recording`, `start_replay`
+    pub recorder: InputRecorder,
+    /// seed that systems should create their rngs from, so that a replay
+    /// of a recording produces the same results. saved in every recording.
+    pub seed: u64,
 }
 
 impl<U: UserState<T>, T: TextureEnum> State<U, T> {
@@ -36,7 +64,17 @@ impl<U: UserState<T>, T: TextureEnum> State<U, T> {
             clear_color: BLACK,
             coords: Default::default(),
             world: Default::default(),
+            fixed: Default::default(),
+            events: Default::default(),
+            resources: Default::default(),
+            time: Default::default(),
+            input: Default::default(),
+            actions: Default::default(),
+            recorder: Default::default(),
+            seed: 0,
         };
+        // so that the view can be centered before the first frame, eg: in `U::initialize`
+        s.set_screen_size(Vec2::new(screen_width(), screen_height()));
         U::initialize(&mut s);
         s
     }
@@ -49,67 +87,280 @@ impl<U: UserState<T>, T: TextureEnum> State<U, T> {
             clear_color: BLACK,
             coords: Default::default(),
             world: Default::default(),
+            fixed: Default::default(),
+            events: Default::default(),
+            resources: Default::default(),
+            time: Default::default(),
+            input: Default::default(),
+            actions: Default::default(),
+            recorder: Default::default(),
+            seed: 0,
+        }
+    }
+    /// like `new`, but does not load any textures, so it can be created
+    /// without a window or graphics context. `U::initialize` is still called,
+    /// so it should not rely on textures or the window when used headless.
+    pub fn headless() -> Self {
+        let mut s = Self::empty();
+        U::initialize(&mut s);
+        s
+    }
+    /// runs a single frame of the schedule with the given delta time.
+    /// unlike `run`, this does not clear the screen or wait for the next frame,
+    /// so it can be used to drive systems without a graphics context.
+    pub fn step(&mut self, schedule: &mut Schedule<U, T>, delta_time: f32) {
+        if self.input.screen_size != Vec2::ZERO {
+            self.set_screen_size(self.input.screen_size);
+        }
+        schedule.run_frame(self, delta_time);
+    }
+    /// sets the screen size of the input and the viewport size of the main view,
+    /// which always covers the whole screen. `new` and `step` call this, so it is
+    /// only needed when running headless, before the first frame.
+    pub fn set_screen_size(&mut self, size: Vec2) {
+        self.input.screen_size = size;
+        self.coords.viewport_size = size;
+    }
+    /// true while any binding of the action is held down
+    pub fn action_down(&self, action: Action) -> bool {
+        self.actions.is_down(action, &self.input)
+    }
+    /// true if any binding of the action was pressed this frame
+    pub fn action_pressed(&self, action: Action) -> bool {
+        self.actions.is_pressed(action, &self.input)
+    }
+    /// true if any binding of the action was released this frame
+    pub fn action_released(&self, action: Action) -> bool {
+        self.actions.is_released(action, &self.input)
+    }
+    /// start recording the input of every frame, discarding any previous recording.
+    /// the current seed is saved in the recording.
+    pub fn start_recording(&mut self) {
+        self.recorder = InputRecorder::Recording(InputRecording { seed: self.seed, frames: vec![] });
+    }
+    /// returns the recording if one was in progress
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        match std::mem::take(&mut self.recorder) {
+            InputRecorder::Recording(recording) => Some(recording),
+            other => {
+                self.recorder = other;
+                None
+            }
+        }
+    }
+    /// replaces the live input with the recorded input until every frame of the recording
+    /// was replayed. the seed is set to the seed of the recording, so this should be called
+    /// before any rng is created from the seed, eg: before the first frame runs.
+    pub fn start_replay(&mut self, recording: InputRecording) {
+        self.seed = recording.seed;
+        self.recorder = InputRecorder::Replaying { recording, frame: 0 };
+    }
+    /// inserts the resource, replacing any previous resource of the same type.
+    pub fn insert_resource<R: 'static>(&mut self, resource: R) {
+        self.resources.insert(resource);
+    }
+    pub fn remove_resource<R: 'static>(&mut self) -> Option<R> {
+        self.resources.remove()
+    }
+    pub fn has_resource<R: 'static>(&self) -> bool {
+        self.resources.contains::<R>()
+    }
+    /// panics if the resource was never inserted. see `get_resource` for a non-panicking version.
+    pub fn resource<R: 'static>(&self) -> &R {
+        match self.resources.get() {
+            Some(r) => r,
+            None => panic!("resource {} does not exist", std::any::type_name::<R>()),
+        }
+    }
+    /// panics if the resource was never inserted. see `get_resource_mut` for a non-panicking version.
+    pub fn resource_mut<R: 'static>(&mut self) -> &mut R {
+        match self.resources.get_mut() {
+            Some(r) => r,
+            None => panic!("resource {} does not exist", std::any::type_name::<R>()),
+        }
+    }
+    pub fn get_resource<R: 'static>(&self) -> Option<&R> {
+        self.resources.get()
+    }
+    pub fn get_resource_mut<R: 'static>(&mut self) -> Option<&mut R> {
+        self.resources.get_mut()
+    }
+    /// the current app state. panics if the schedule was not given this app state,
+    /// see: `Schedule::with_app_state`
+    pub fn app_state<S: AppStateEnum>(&self) -> S {
+        self.resource::<AppState<S>>().current()
+    }
+    /// queues a transition to the given app state. the transition
+    /// is applied at the start of the next simulation step.
+    /// panics if the schedule was not given this app state.
+    pub fn set_app_state<S: AppStateEnum>(&mut self, next: S) {
+        self.resource_mut::<AppState<S>>().set(next);
+    }
+}
+
+/// bookkeeping for running simulation systems at a fixed rate
+/// regardless of the frame rate. see: `Schedule::with_fixed_timestep`.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedTimestep {
+    /// how many seconds a single simulation step represents
+    pub step: f32,
+    /// frame time that has not yet been consumed by a simulation step
+    pub accumulator: f32,
+    /// how far (0.0 - 1.0) the current frame is between the previous
+    /// simulation step and the next one. draw systems can use this to interpolate.
+    pub alpha: f32,
+    /// max number of simulation steps to run in a single frame.
+    /// if a frame takes longer than this many steps, the leftover time is dropped
+    /// so that the simulation does not keep falling further behind.
+    pub max_steps: usize,
+}
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::from_hz(60.0)
+    }
+}
+
+impl FixedTimestep {
+    /// panics if hz is not positive and finite
+    pub fn from_hz(hz: f32) -> Self {
+        assert!(hz > 0.0 && hz.is_finite(), "fixed timestep hz must be positive and finite, got {}", hz);
+        Self {
+            step: 1.0 / hz,
+            accumulator: 0.0,
+            alpha: 0.0,
+            max_steps: 8,
         }
     }
+    /// adds the frame time to the accumulator and returns
+    /// how many simulation steps should be ran this frame.
+    /// updates alpha to reflect the leftover time.
+    pub fn advance(&mut self, frame_dt: f32) -> usize {
+        self.accumulator += frame_dt;
+        let mut steps = 0;
+        while self.accumulator >= self.step && steps < self.max_steps {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        if self.accumulator >= self.step {
+            self.accumulator %= self.step;
+        }
+        self.alpha = self.accumulator / self.step;
+        steps
+    }
 }
 
 /// main game loop. runs your game according to your
-/// initial state data + the system functions you defined.
+/// initial state data + the schedule of systems you defined.
 /// provide your initialized state+world,
-/// a list of system functions to run in order,
+/// the schedule of systems to run,
 /// and how many frames to output debug timings. if set to 0,
 /// no debug timings are emitted.
 pub async fn run<U: UserState<T>, T: TextureEnum>(
     mut state: State<U, T>,
-    systems: &'static[System<U, T>],
+    mut schedule: Schedule<U, T>,
     debug_frame_count: usize,
 ) {
-    let frames_before_debug = debug_frame_count;
-    let frames_before_debug_f64 = frames_before_debug as f64;
-    let mut debug_frame_count = 0;
-    let mut debug_timings: Vec<f64> = systems.iter().map(|_| 0.0).collect();
-    let mut longest_sys_name = 0;
-    for (_, sys_name) in systems.iter() {
-        if sys_name.len() > longest_sys_name {
-            longest_sys_name = sys_name.len();
-        }
-    }
-
+    schedule.set_debug_frame_count(debug_frame_count);
     loop {
         clear_background(state.clear_color);
-        let delta_time = get_frame_time();
-        for (sys_index, (sys_fn, _sys_name)) in systems.iter().enumerate() {
-            let start = macroquad::time::get_time();
-            sys_fn(&mut state, delta_time);
-            let end = macroquad::time::get_time();
-            if frames_before_debug > 0 {
-                debug_timings[sys_index] += (end - start) * 1000.0;
-            }
-        }
-
-        debug_frame_count += 1;
-        if debug_frame_count >= frames_before_debug && frames_before_debug > 0 {
-            let total_time = debug_timings.iter().sum::<f64>() / frames_before_debug_f64;
-            let mut debug_timings_sorted: Vec<_> = debug_timings.drain(..).enumerate().map(|(i, x)| (i, x)).collect();
-            debug_timings_sorted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-            for (index, time) in debug_timings_sorted {
-                if time < 0.001 {
-                    break;
-                }
-                let (_, sys_name) = systems[index];
-                let avg_time = time / frames_before_debug_f64;
-                let percent = (avg_time / total_time) * 100.0;
-                // 25 bars max, so we divide by 4.0
-                let num_bars = (percent / 4.0).max(1.0);
-                let padding = " ".repeat(longest_sys_name - sys_name.len());
-
-                let bars = "\u{2588}".repeat(num_bars as usize);
-                macroquad::logging::warn!("{}{} {:0.4}ms {}", sys_name, padding, avg_time, bars);
-            }
-            macroquad::logging::warn!("");
-            debug_timings = systems.iter().map(|_| 0.0).collect();
-            debug_frame_count = 0;
-        }
+        let (delta_time, input) = state.recorder.next_frame(get_frame_time(), InputState::from_macroquad());
+        state.input = input;
+        state.step(&mut schedule, delta_time);
         next_frame().await;
     }
 }
+
+/// runs the schedule for a given number of frames without a window,
+/// passing the same delta time to every frame. `state.input` is kept between frames,
+/// except that keys/buttons stop being pressed/released after the first frame,
+/// see: `InputState::next_frame`. returns the state so that the results can be inspected, eg:
+/// ```
+/// let schedule = Schedule::new().with_system(Stage::PostUpdate, sys!(update_children_transforms));
+/// let state = run_headless(State::<MyState, Textures>::headless(), schedule, 10, 1.0 / 60.0);
+/// ```
+pub fn run_headless<U: UserState<T>, T: TextureEnum>(
+    mut state: State<U, T>,
+    mut schedule: Schedule<U, T>,
+    frames: usize,
+    delta_time: f32,
+) -> State<U, T> {
+    for _ in 0..frames {
+        state.step(&mut schedule, delta_time);
+        state.input.next_frame();
+    }
+    state
+}
+
+/// replays every frame of the recording without a window, using the recorded
+/// delta times and input. returns the state so that the results can be inspected, eg:
+/// ```
+/// let recording = InputRecording::load_from_file("session.input")?;
+/// let state = run_replay_headless(State::<MyState, Textures>::headless(), get_schedule(), recording);
+/// ```
+pub fn run_replay_headless<U: UserState<T>, T: TextureEnum>(
+    mut state: State<U, T>,
+    mut schedule: Schedule<U, T>,
+    recording: InputRecording,
+) -> State<U, T> {
+    let frames = recording.frames.len();
+    state.start_replay(recording);
+    for _ in 0..frames {
+        let (delta_time, input) = state.recorder.next_frame(0.0, InputState::default());
+        state.input = input;
+        state.step(&mut schedule, delta_time);
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn center_to_before_the_first_frame() {
+        let mut s = TestState::headless();
+        s.set_screen_size(Vec2::new(100.0, 100.0));
+        s.coords.center_to(500.0, 500.0);
+        assert_eq!(s.coords.view_center(), Vec2::new(500.0, 500.0));
+        assert_eq!((s.coords.pan_x, s.coords.pan_y), (450.0, 450.0));
+    }
+
+    #[test]
+    fn fixed_timestep_runs_whole_steps_and_keeps_the_rest() {
+        let mut fixed = FixedTimestep::from_hz(10.0);
+        assert_eq!(fixed.advance(0.25), 2);
+        assert!((fixed.accumulator - 0.05).abs() < 1e-5);
+        assert!((fixed.alpha - 0.5).abs() < 1e-4);
+        // the leftover time carries over into the next frame
+        assert_eq!(fixed.advance(0.06), 1);
+        assert!((fixed.accumulator - 0.01).abs() < 1e-5);
+    }
+
+    #[test]
+    fn fixed_timestep_runs_no_steps_on_short_frames() {
+        let mut fixed = FixedTimestep::from_hz(10.0);
+        assert_eq!(fixed.advance(0.05), 0);
+        assert!((fixed.alpha - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn fixed_timestep_drops_time_beyond_max_steps() {
+        let mut fixed = FixedTimestep::from_hz(10.0);
+        assert_eq!(fixed.advance(10.0), fixed.max_steps);
+        assert!(fixed.accumulator < fixed.step);
+        assert!((0.0..1.0).contains(&fixed.alpha));
+    }
+
+    #[test]
+    #[should_panic]
+    fn fixed_timestep_rejects_zero_hz() {
+        FixedTimestep::from_hz(0.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn fixed_timestep_rejects_nan_hz() {
+        FixedTimestep::from_hz(f32::NAN);
+    }
+}
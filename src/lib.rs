@@ -1,3 +1,6 @@
 pub mod system;
 pub mod data;
 pub mod components;
+
+#[cfg(test)]
+mod testing;
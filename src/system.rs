@@ -1,2 +1,8 @@
 pub mod stateful;
 pub mod stateless;
+pub mod schedule;
+pub mod conditions;
+pub mod profiler;
+pub mod camera;
+pub mod hierarchy;
+pub mod animation;
@@ -0,0 +1,78 @@
+//! Sprite sheet animations: entities with a `Drawable::Animation` are advanced by
+//! `advance_animations`, which uses the simulation delta time, so animations pause
+//! and slow down with the simulation. Example:
+//! ```
+//! Schedule::new()
+//!     .with_system(Stage::Update, sys!(advance_animations))
+//!     .with_system(Stage::Render, sys!(draw))
+//!
+//! // despawn explosions once they finished playing:
+//! fn despawn_explosions(s: &mut GameState, _dt: f32) {
+//!     for finished in s.events.iter::<AnimationFinished>().copied().collect::<Vec<_>>() {
+//!         let _ = s.world.despawn(finished.entity);
+//!     }
+//! }
+//! ```
+
+use hecs::*;
+
+use crate::{
+    components::*,
+    data::{
+        world::*,
+        loading::*,
+    },
+};
+
+/// sent when a `Once` animation reaches its last frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnimationFinished {
+    pub entity: Entity,
+}
+
+/// sent when a `Loop` or `PingPong` animation is back at its first frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnimationLooped {
+    pub entity: Entity,
+    /// how many cycles were completed this frame, more than 1 if the delta time was longer than a cycle
+    pub cycles: usize,
+}
+
+/// advances every `Drawable::Animation` by the delta time, and sends
+/// `AnimationFinished` and `AnimationLooped` events
+pub fn advance_animations<U: UserState<T>, T: TextureEnum>(s: &mut State<U, T>, dt: f32) {
+    for (entity, drawable) in s.world.query_mut::<&mut Drawable>() {
+        let d = match drawable {
+            Drawable::Animation { d, .. } => d,
+            _ => continue,
+        };
+        match d.advance(dt) {
+            0 => {}
+            _ if d.mode == AnimationMode::Once => s.events.send(AnimationFinished { entity }),
+            cycles => s.events.send(AnimationLooped { entity, cycles }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::prelude::*;
+
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn only_once_animations_finish() {
+        let mut s = TestState::empty();
+        let frames = vec![Rect::new(0.0, 0.0, 8.0, 8.0), Rect::new(8.0, 0.0, 8.0, 8.0)];
+        let once = SpriteAnimation::from_texture(Texture2D::empty(), frames.clone(), 0.25).with_mode(AnimationMode::Once);
+        let looping = SpriteAnimation::from_texture(Texture2D::empty(), frames, 0.25);
+        let once = s.world.spawn((Drawable::animation(once),));
+        let looping = s.world.spawn((Drawable::animation(looping),));
+        advance_animations(&mut s, 0.5);
+        let finished: Vec<_> = s.events.iter::<AnimationFinished>().copied().collect();
+        let looped: Vec<_> = s.events.iter::<AnimationLooped>().copied().collect();
+        assert_eq!(finished, vec![AnimationFinished { entity: once }]);
+        assert_eq!(looped, vec![AnimationLooped { entity: looping, cycles: 1 }]);
+    }
+}
@@ -0,0 +1,223 @@
+//! Automatic camera movement on top of `CoordTransform`: following an entity,
+//! and trauma based screen shake. Both are optional resources, and their systems
+//! do nothing while the resource does not exist. Example:
+//! ```
+//! s.insert_resource(CameraFollow::new(player).with_damping(4.0).with_dead_zone(Vec2::new(40.0, 30.0)));
+//! s.insert_resource(ScreenShake::default());
+//! // when something explodes:
+//! s.resource_mut::<ScreenShake>().add_trauma(0.5);
+//!
+//! Schedule::new()
+//!     .with_system(Stage::Render, sys!(handle_pan))
+//!     .with_system(Stage::Render, sys!(follow_camera_target))
+//!     .with_system(Stage::Render, sys!(update_screen_shake))
+//!     .with_system(Stage::Render, sys!(draw))
+//! ```
+//! Both systems should run after `handle_pan` and before `draw`, all in the render stage
+//! so that they keep running while the simulation is paused, and see the same frame's input.
+
+use bracket_noise::prelude::*;
+use hecs::*;
+use macroquad::prelude::*;
+
+use crate::{
+    components::*,
+    data::{
+        world::*,
+        loading::*,
+    },
+};
+
+/// moves the view towards an entity's `Transform`. when the user pans the view with `handle_pan`,
+/// following stops, and resumes after `resume_after` seconds without panning, or when `resume` is called.
+pub struct CameraFollow {
+    pub target: Option<Entity>,
+    /// how quickly the view catches up with the target, per second. 0.0 snaps to the target.
+    pub damping: f32,
+    /// half the size of a world space box around the view center.
+    /// the view only moves once the target leaves this box.
+    pub dead_zone: Vec2,
+    /// how many seconds of the target's velocity to look ahead of it.
+    pub look_ahead: f32,
+    /// seconds without manual panning after which following resumes.
+    /// None keeps manual control until `resume` is called.
+    pub resume_after: Option<f32>,
+    /// seconds since the user last panned, while following is suspended
+    manual_for: Option<f32>,
+    last_position: Option<Vec2>,
+    velocity: Vec2,
+}
+
+impl CameraFollow {
+    pub fn new(target: Entity) -> Self {
+        Self {
+            target: Some(target),
+            damping: 5.0,
+            dead_zone: Vec2::ZERO,
+            look_ahead: 0.0,
+            resume_after: Some(2.0),
+            manual_for: None,
+            last_position: None,
+            velocity: Vec2::ZERO,
+        }
+    }
+    pub fn with_damping(mut self, damping: f32) -> Self {
+        self.damping = damping;
+        self
+    }
+    pub fn with_dead_zone(mut self, dead_zone: Vec2) -> Self {
+        self.dead_zone = dead_zone;
+        self
+    }
+    pub fn with_look_ahead(mut self, look_ahead: f32) -> Self {
+        self.look_ahead = look_ahead;
+        self
+    }
+    pub fn with_resume_after(mut self, resume_after: Option<f32>) -> Self {
+        self.resume_after = resume_after;
+        self
+    }
+    /// follow a different entity. the velocity of the previous target is discarded.
+    pub fn set_target(&mut self, target: Option<Entity>) {
+        self.target = target;
+        self.last_position = None;
+        self.velocity = Vec2::ZERO;
+    }
+    /// true while the user has control of the view
+    pub fn is_suspended(&self) -> bool {
+        self.manual_for.is_some()
+    }
+    /// give control to the user until `resume` is called, or `resume_after` elapses
+    pub fn suspend(&mut self) {
+        self.manual_for = Some(0.0);
+    }
+    /// take control back from the user
+    pub fn resume(&mut self) {
+        self.manual_for = None;
+    }
+}
+
+/// moves `State::coords` towards the target of the `CameraFollow` resource, if it exists.
+/// must run after `handle_pan` in the same stage, as it reads `CoordTransform::panned_by_user`.
+pub fn follow_camera_target<U: UserState<T>, T: TextureEnum>(s: &mut State<U, T>, dt: f32) {
+    let follow = match s.resources.get_mut::<CameraFollow>() {
+        Some(follow) => follow,
+        None => return,
+    };
+    let coords = &mut s.coords;
+    let position = match follow.target.and_then(|e| s.world.get::<&Transform>(e).ok()) {
+        Some(transform) => transform.d.transform_point2(Vec2::ZERO),
+        None => return,
+    };
+    if dt > 0.0 {
+        if let Some(last) = follow.last_position {
+            follow.velocity = (position - last) / dt;
+        }
+        follow.last_position = Some(position);
+    }
+
+    if coords.panned_by_user {
+        follow.suspend();
+    }
+    if let Some(manual_for) = &mut follow.manual_for {
+        if !coords.panned_by_user {
+            *manual_for += dt;
+        }
+        match follow.resume_after {
+            Some(resume_after) if *manual_for >= resume_after => follow.resume(),
+            _ => return,
+        }
+    }
+
+    let desired = position + follow.velocity * follow.look_ahead;
+    let center = coords.view_center();
+    let delta = desired - center;
+    // only move by how far the target is outside of the dead zone
+    let delta = Vec2::new(
+        delta.x.signum() * (delta.x.abs() - follow.dead_zone.x).max(0.0),
+        delta.y.signum() * (delta.y.abs() - follow.dead_zone.y).max(0.0),
+    );
+    let t = if follow.damping <= 0.0 {
+        1.0
+    } else {
+        // exponential smoothing, so that the damping is independent of the frame rate
+        1.0 - (-follow.damping * dt).exp()
+    };
+    let new_center = center + delta * t;
+    coords.center_to(new_center.x, new_center.y);
+}
+
+/// trauma based screen shake: the view shakes by trauma squared, so small amounts
+/// of trauma barely shake, and the shake fades out smoothly as trauma decays.
+pub struct ScreenShake {
+    /// 0.0 - 1.0. see: `add_trauma`
+    pub trauma: f32,
+    /// how much trauma is removed per second
+    pub decay: f32,
+    /// the largest offset at full trauma, in screen pixels
+    pub max_offset: f32,
+    /// how quickly the shake changes direction
+    pub frequency: f32,
+    noise: FastNoise,
+    time: f32,
+}
+
+impl Default for ScreenShake {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl ScreenShake {
+    /// the seed decides the shake pattern, eg: `ScreenShake::new(s.seed)`
+    pub fn new(seed: u64) -> Self {
+        let mut noise = FastNoise::seeded(seed);
+        noise.set_noise_type(NoiseType::Perlin);
+        noise.set_frequency(1.0);
+        Self {
+            trauma: 0.0,
+            decay: 1.0,
+            max_offset: 20.0,
+            frequency: 15.0,
+            noise,
+            time: 0.0,
+        }
+    }
+    pub fn with_decay(mut self, decay: f32) -> Self {
+        self.decay = decay;
+        self
+    }
+    pub fn with_max_offset(mut self, max_offset: f32) -> Self {
+        self.max_offset = max_offset;
+        self
+    }
+    pub fn with_frequency(mut self, frequency: f32) -> Self {
+        self.frequency = frequency;
+        self
+    }
+    /// adds trauma, up to 1.0
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+    /// the offset for the current trauma and time
+    pub fn offset(&self) -> Vec2 {
+        let shake = self.trauma * self.trauma;
+        let t = self.time * self.frequency;
+        // sample the noise along two different rows, so that x and y move independently
+        let x = self.noise.get_noise(t, 0.0);
+        let y = self.noise.get_noise(t, 100.0);
+        Vec2::new(x, y) * self.max_offset * shake
+    }
+}
+
+/// decays the trauma of the `ScreenShake` resource, if it exists,
+/// and sets `CoordTransform::shake_offset` accordingly.
+pub fn update_screen_shake<U: UserState<T>, T: TextureEnum>(s: &mut State<U, T>, dt: f32) {
+    let shake = match s.resources.get_mut::<ScreenShake>() {
+        Some(shake) => shake,
+        None => return,
+    };
+    shake.time += dt;
+    s.coords.shake_offset = shake.offset();
+    shake.trauma = (shake.trauma - shake.decay * dt).max(0.0);
+}
@@ -0,0 +1,69 @@
+//! Common run conditions that can be attached to a system via `System::run_if`.
+//! A run condition is checked every time the system would run, and
+//! the system is skipped if the condition returns false.
+//! To run a system only once, see: `System::run_once`
+
+use crate::data::{
+    world::*,
+    loading::*,
+    app_state::*,
+};
+
+/// the system runs only while the flag returns true, eg:
+/// ```
+/// sys!(generate_tiles_voronoi).run_if(while_flag(|s: &GameState| s.usr.voronoi_tiling.ready_to_tile))
+/// ```
+pub fn while_flag<U: UserState<T>, T: TextureEnum>(
+    flag: impl Fn(&State<U, T>) -> bool,
+) -> impl FnMut(&State<U, T>, f32) -> bool {
+    move |s, _| flag(s)
+}
+
+/// the system runs only while a resource of this type exists, eg:
+/// ```
+/// sys!(generate_tiles_voronoi).run_if(resource_exists::<VoronoiTiling, _, _>())
+/// ```
+pub fn resource_exists<R: 'static, U: UserState<T>, T: TextureEnum>() -> impl FnMut(&State<U, T>, f32) -> bool {
+    |s, _| s.has_resource::<R>()
+}
+
+/// the system runs only while the resource exists and the flag returns true for it, eg:
+/// ```
+/// sys!(generate_tiles_voronoi).run_if(while_resource_flag(|tiling: &VoronoiTiling| tiling.ready_to_tile))
+/// ```
+pub fn while_resource_flag<R: 'static, U: UserState<T>, T: TextureEnum>(
+    flag: impl Fn(&R) -> bool,
+) -> impl FnMut(&State<U, T>, f32) -> bool {
+    move |s, _| s.get_resource::<R>().map(&flag).unwrap_or(false)
+}
+
+/// the system runs only while in the given app state. this is useful for systems
+/// in stages other than update, as on_update systems always run after the update stage.
+pub fn in_app_state<U: UserState<T>, T: TextureEnum, S: AppStateEnum>(app_state: S) -> impl FnMut(&State<U, T>, f32) -> bool {
+    move |s, _| s.get_resource::<AppState<S>>().map(|a| a.current() == app_state).unwrap_or(false)
+}
+
+/// the system runs at most once every N seconds. the time is accumulated from
+/// the delta time passed to the system, so the first run happens after N seconds have elapsed.
+pub fn every_seconds<U: UserState<T>, T: TextureEnum>(seconds: f32) -> impl FnMut(&State<U, T>, f32) -> bool {
+    let mut elapsed = 0.0;
+    move |_, dt| {
+        elapsed += dt;
+        if elapsed < seconds {
+            return false;
+        }
+        elapsed -= seconds;
+        // dont run multiple frames in a row to catch up after a long frame
+        if elapsed > seconds {
+            elapsed %= seconds;
+        }
+        true
+    }
+}
+
+/// inverts a condition
+pub fn not<U: UserState<T>, T: TextureEnum>(
+    mut condition: impl FnMut(&State<U, T>, f32) -> bool,
+) -> impl FnMut(&State<U, T>, f32) -> bool {
+    move |s, dt| !condition(s, dt)
+}
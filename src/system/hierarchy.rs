@@ -0,0 +1,424 @@
+//! The transform hierarchy: entities with a `Parent` have their `Transform` computed from
+//! their parent's `Transform` and their `Parent::local_transform`. Transforms are propagated
+//! from the roots to the leaves once per frame, and transforms that did not change since the
+//! last frame are not recomputed. See: `update_children_transforms`.
+//! Every parent has a `Children` component listing its children. Example:
+//! ```
+//! let unit = s.world.spawn((Transform::from_scale_angle_position(1.0, 0.0, (100.0, 0.0)),));
+//! let sprite = s.world.spawn((Transform::from_scale_angle_position(1.0, 0.0, (110.0, 0.0)), Layer1));
+//! set_parent(&mut s.world, sprite, unit)?; // the sprite stays at (110, 0)
+//! despawn_recursive(&mut s.world, unit); // despawns the sprite too
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use hecs::*;
+use macroquad::prelude::*;
+
+use crate::{
+    components::*,
+    data::{
+        world::*,
+        loading::*,
+    },
+};
+
+/// sent as an event when an entity's transform can not be propagated.
+/// each problem is reported once, and again only if it was fixed and then reappears.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HierarchyError {
+    /// the entity's parent was despawned, or doesn't have a `Transform`
+    DanglingParent { entity: Entity, parent: Entity },
+    /// the entity is its own ancestor
+    ParentCycle { entity: Entity },
+}
+
+/// what was propagated last frame, so that unchanged subtrees can be skipped.
+/// stored as a resource, and created by `update_children_transforms` when missing.
+#[derive(Default)]
+pub struct TransformCache {
+    /// the transform of every entity in the hierarchy as of the last propagation
+    world: HashMap<Entity, Affine2>,
+    /// the local transform and parent of every child as of the last propagation
+    local: HashMap<Entity, (Entity, Affine2)>,
+    reported: HashSet<HierarchyError>,
+    /// scratch buffer, kept to avoid allocating every frame
+    children: HashMap<Entity, Vec<Entity>>,
+}
+
+impl TransformCache {
+    /// forget everything, so that the whole hierarchy is recomputed on the next propagation
+    pub fn clear(&mut self) {
+        self.world.clear();
+        self.local.clear();
+    }
+}
+
+/// propagates every root's transform down to its descendants. a transform is only recomputed if
+/// a transform on the path to it changed since the last frame, or its parent changed.
+/// every entity in the hierarchy is still visited each frame, as hecs has no change detection,
+/// so a change deep in an unchanged subtree can only be found by looking at it. the cost is
+/// linear in the number of children, about a few hash map lookups each.
+/// dangling parents and parent cycles are logged and sent as `HierarchyError` events.
+/// children of despawned parents have their `Parent` removed, and keep their last transform.
+/// the `Children` of every parent are updated to match the `Parent`s.
+pub fn propagate_transforms<U: UserState<T>, T: TextureEnum>(s: &mut State<U, T>) {
+    let mut cache = s.resources.remove::<TransformCache>().unwrap_or_default();
+    let errors = propagate(&mut s.world, &mut cache);
+    for error in errors {
+        warn!("transform hierarchy: {:?}", error);
+        s.events.send(error);
+    }
+    s.resources.insert(cache);
+}
+
+/// returns the errors that were not reported before
+fn propagate(world: &mut World, cache: &mut TransformCache) -> Vec<HierarchyError> {
+    let mut children = std::mem::take(&mut cache.children);
+    children.values_mut().for_each(|c| c.clear());
+    let mut child_count = 0;
+    for (entity, parent) in world.query_mut::<&Parent>() {
+        children.entry(parent.parent).or_default().push(entity);
+        child_count += 1;
+    }
+
+    let mut visited = HashSet::with_capacity(child_count);
+    // (entity, parent's transform, whether the parent's transform changed)
+    let mut stack: Vec<(Entity, Affine2, bool)> = vec![];
+    for (root, kids) in children.iter() {
+        if kids.is_empty() || world.get::<&Parent>(*root).is_ok() {
+            continue;
+        }
+        let root_transform = match world.get::<&Transform>(*root) {
+            Ok(transform) => transform.d,
+            // reported below as dangling
+            Err(_) => continue,
+        };
+        let changed = cache.world.insert(*root, root_transform) != Some(root_transform);
+        stack.extend(kids.iter().map(|c| (*c, root_transform, changed)));
+    }
+    while let Some((entity, parent_transform, parent_changed)) = stack.pop() {
+        if !visited.insert(entity) {
+            continue;
+        }
+        let parent = match world.get::<&Parent>(entity) {
+            Ok(parent) => (parent.parent, parent.local_transform.d),
+            Err(_) => continue,
+        };
+        let local_changed = cache.local.insert(entity, parent) != Some(parent);
+        let cached = cache.world.get(&entity).copied();
+        let mut transform = world.get::<&mut Transform>(entity).ok();
+        // the transform was set directly since the last propagation, so it has to be overwritten
+        let overwritten = matches!((&transform, cached), (Some(t), Some(c)) if t.d != c);
+        let changed = parent_changed || local_changed || overwritten || cached.is_none();
+        let absolute = if changed {
+            let absolute = parent_transform * parent.1;
+            if let Some(transform) = &mut transform {
+                transform.d = absolute;
+            }
+            cache.world.insert(entity, absolute);
+            absolute
+        } else {
+            cached.unwrap_or(parent_transform * parent.1)
+        };
+        drop(transform);
+        if let Some(kids) = children.get(&entity) {
+            stack.extend(kids.iter().map(|c| (*c, absolute, changed)));
+        }
+    }
+
+    // every child that wasnt reached from a root is either below a dangling parent or in a cycle
+    let mut errors = HashSet::new();
+    for (entity, parent) in world.query::<&Parent>().iter() {
+        if visited.contains(&entity) {
+            continue;
+        }
+        if !world.contains(parent.parent) || (world.get::<&Parent>(parent.parent).is_err() && world.get::<&Transform>(parent.parent).is_err()) {
+            errors.insert(HierarchyError::DanglingParent { entity, parent: parent.parent });
+        } else if is_in_cycle(world, entity) {
+            errors.insert(HierarchyError::ParentCycle { entity });
+        }
+        cache.world.remove(&entity);
+        cache.local.remove(&entity);
+    }
+    let mut cb = CommandBuffer::new();
+    for error in errors.iter() {
+        if let HierarchyError::DanglingParent { entity, parent } = error {
+            if !world.contains(*parent) {
+                cb.remove_one::<Parent>(*entity);
+            }
+        }
+    }
+    sync_children(world, &children, &mut cb);
+    cb.run_on(world);
+
+    // forget everything that is no longer part of the hierarchy
+    cache.world.retain(|e, _| visited.contains(e) || children.get(e).map(|c| !c.is_empty()).unwrap_or(false));
+    cache.local.retain(|e, _| visited.contains(e));
+    children.retain(|_, c| !c.is_empty());
+    cache.children = children;
+
+    let new_errors = errors.difference(&cache.reported).copied().collect();
+    cache.reported = errors;
+    new_errors
+}
+
+/// inserts, updates or removes the `Children` of every entity so that they match the parents.
+/// the existing order of children is kept, and new children are added at the end.
+fn sync_children(world: &World, children: &HashMap<Entity, Vec<Entity>>, cb: &mut CommandBuffer) {
+    for (parent, kids) in children.iter() {
+        if kids.is_empty() || !world.contains(*parent) {
+            continue;
+        }
+        match world.get::<&mut Children>(*parent) {
+            Ok(mut existing) => {
+                let matches = existing.d.len() == kids.len() && kids.iter().all(|k| existing.d.contains(k));
+                if !matches {
+                    existing.d.retain(|e| kids.contains(e));
+                    for kid in kids.iter() {
+                        if !existing.d.contains(kid) {
+                            existing.d.push(*kid);
+                        }
+                    }
+                }
+            }
+            Err(_) => cb.insert_one(*parent, Children { d: kids.clone() }),
+        }
+    }
+    for (entity, _) in world.query::<&Children>().iter() {
+        if children.get(&entity).map(|c| c.is_empty()).unwrap_or(true) {
+            cb.remove_one::<Children>(entity);
+        }
+    }
+}
+
+/// the transform of the entity, or identity if it has none
+fn transform_of(world: &World, entity: Entity) -> Affine2 {
+    world.get::<&Transform>(entity).map(|t| t.d).unwrap_or(Affine2::IDENTITY)
+}
+
+/// attaches the child to the parent, replacing its previous parent if any.
+/// the child's local transform is set so that it keeps its current world transform.
+/// fails if the parent doesn't exist, if the parent is the child or one of its descendants,
+/// or if the parent's ancestors form a cycle.
+pub fn set_parent(world: &mut World, child: Entity, parent: Entity) -> Result<(), HierarchyError> {
+    if !world.contains(parent) || !world.contains(child) {
+        return Err(HierarchyError::DanglingParent { entity: child, parent });
+    }
+    let mut seen = HashSet::new();
+    let mut ancestor = Some(parent);
+    while let Some(current) = ancestor {
+        // the child is an ancestor of the parent, or the parent is already in a cycle
+        if current == child || !seen.insert(current) {
+            return Err(HierarchyError::ParentCycle { entity: child });
+        }
+        ancestor = world.get::<&Parent>(current).ok().map(|p| p.parent);
+    }
+    remove_from_parents_children(world, child);
+    let local = transform_of(world, parent).inverse() * transform_of(world, child);
+    let _ = world.insert_one(child, Parent { parent, local_transform: Transform { d: local } });
+    if let Ok(mut children) = world.get::<&mut Children>(parent) {
+        if !children.d.contains(&child) {
+            children.d.push(child);
+        }
+        return Ok(());
+    }
+    let _ = world.insert_one(parent, Children { d: vec![child] });
+    Ok(())
+}
+
+/// removes the child from its parent. the child keeps its current world transform, and becomes a root.
+pub fn detach(world: &mut World, child: Entity) {
+    remove_from_parents_children(world, child);
+    let _ = world.remove_one::<Parent>(child);
+}
+
+fn remove_from_parents_children(world: &mut World, child: Entity) {
+    let parent = match world.get::<&Parent>(child) {
+        Ok(parent) => parent.parent,
+        Err(_) => return,
+    };
+    let now_empty = match world.get::<&mut Children>(parent) {
+        Ok(mut children) => {
+            children.d.retain(|e| *e != child);
+            children.d.is_empty()
+        }
+        Err(_) => false,
+    };
+    if now_empty {
+        let _ = world.remove_one::<Children>(parent);
+    }
+}
+
+/// every descendant of the entity, children first, then grandchildren, etc.
+/// found via `Parent`, so this is correct even before `Children` were updated.
+pub fn descendants(world: &World, entity: Entity) -> Vec<Entity> {
+    let mut children: HashMap<Entity, Vec<Entity>> = HashMap::new();
+    for (child, parent) in world.query::<&Parent>().iter() {
+        children.entry(parent.parent).or_default().push(child);
+    }
+    let mut found = vec![];
+    let mut seen = HashSet::from([entity]);
+    let mut index = 0;
+    let mut current = entity;
+    loop {
+        for child in children.get(&current).into_iter().flatten() {
+            if seen.insert(*child) {
+                found.push(*child);
+            }
+        }
+        match found.get(index) {
+            Some(next) => current = *next,
+            None => return found,
+        }
+        index += 1;
+    }
+}
+
+/// despawns the entity and all of its descendants, and removes it from its parent's `Children`
+pub fn despawn_recursive(world: &mut World, entity: Entity) {
+    remove_from_parents_children(world, entity);
+    for descendant in descendants(world, entity) {
+        let _ = world.despawn(descendant);
+    }
+    let _ = world.despawn(entity);
+}
+
+/// true if following the parents of the entity leads back to the entity
+fn is_in_cycle(world: &World, entity: Entity) -> bool {
+    let mut seen = HashSet::new();
+    let mut current = entity;
+    while let Ok(parent) = world.get::<&Parent>(current) {
+        current = parent.parent;
+        if current == entity {
+            return true;
+        }
+        if !seen.insert(current) {
+            // a cycle further up, that the entity is not part of
+            return false;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    fn at(x: f32, y: f32) -> Transform {
+        Transform::from_scale_angle_position(1.0, 0.0, (x, y))
+    }
+
+    fn position(s: &TestState, entity: Entity) -> Vec2 {
+        s.world.get::<&Transform>(entity).unwrap().d.translation
+    }
+
+    fn errors(s: &TestState) -> Vec<HierarchyError> {
+        s.events.iter::<HierarchyError>().copied().collect()
+    }
+
+    #[test]
+    fn propagates_from_root_to_leaf() {
+        let mut s = TestState::empty();
+        let root = s.world.spawn((at(10.0, 0.0),));
+        let child = s.world.spawn((at(0.0, 0.0), Parent { parent: root, local_transform: at(5.0, 0.0) }));
+        let grandchild = s.world.spawn((at(0.0, 0.0), Parent { parent: child, local_transform: at(0.0, 1.0) }));
+        propagate_transforms(&mut s);
+        assert_eq!(position(&s, child), Vec2::new(15.0, 0.0));
+        assert_eq!(position(&s, grandchild), Vec2::new(15.0, 1.0));
+        assert_eq!(s.world.get::<&Children>(root).unwrap().d, vec![child]);
+
+        // moving the root moves the subtree on the next propagation
+        s.world.get::<&mut Transform>(root).unwrap().d = at(0.0, 0.0).d;
+        propagate_transforms(&mut s);
+        assert_eq!(position(&s, grandchild), Vec2::new(5.0, 1.0));
+        assert!(errors(&s).is_empty());
+    }
+
+    #[test]
+    fn finds_changes_deep_in_an_unchanged_chain() {
+        let mut s = TestState::empty();
+        let root = s.world.spawn((at(0.0, 0.0),));
+        let mut leaf = root;
+        for _ in 0..10_000 {
+            leaf = s.world.spawn((at(0.0, 0.0), Parent { parent: leaf, local_transform: at(1.0, 0.0) }));
+        }
+        propagate_transforms(&mut s);
+        assert_eq!(position(&s, leaf), Vec2::new(10_000.0, 0.0));
+        // nothing above the leaf changed, but the leaf still has to be visited to see its change
+        s.world.get::<&mut Parent>(leaf).unwrap().local_transform = at(2.0, 0.0);
+        propagate_transforms(&mut s);
+        assert_eq!(position(&s, leaf), Vec2::new(10_001.0, 0.0));
+    }
+
+    #[test]
+    fn reports_dangling_parents_and_detaches_them() {
+        let mut s = TestState::empty();
+        let root = s.world.spawn((at(10.0, 0.0),));
+        let child = s.world.spawn((at(0.0, 0.0), Parent { parent: root, local_transform: at(5.0, 0.0) }));
+        propagate_transforms(&mut s);
+        s.world.despawn(root).unwrap();
+        propagate_transforms(&mut s);
+        assert_eq!(errors(&s), vec![HierarchyError::DanglingParent { entity: child, parent: root }]);
+        // the child keeps its last transform, and becomes a root
+        assert!(s.world.get::<&Parent>(child).is_err());
+        assert_eq!(position(&s, child), Vec2::new(15.0, 0.0));
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let mut s = TestState::empty();
+        let a = s.world.spawn((at(0.0, 0.0),));
+        let b = s.world.spawn((at(0.0, 0.0), Parent { parent: a, local_transform: at(1.0, 0.0) }));
+        s.world.insert_one(a, Parent { parent: b, local_transform: at(1.0, 0.0) }).unwrap();
+        propagate_transforms(&mut s);
+        let mut reported = errors(&s);
+        reported.sort_by_key(|e| format!("{:?}", e));
+        let mut expected = vec![HierarchyError::ParentCycle { entity: a }, HierarchyError::ParentCycle { entity: b }];
+        expected.sort_by_key(|e| format!("{:?}", e));
+        assert_eq!(reported, expected);
+        // not reported again while the cycle stays
+        s.events.update();
+        s.events.update();
+        propagate_transforms(&mut s);
+        assert!(errors(&s).is_empty());
+    }
+
+    #[test]
+    fn set_parent_keeps_the_world_position_and_rejects_cycles() {
+        let mut s = TestState::empty();
+        let parent = s.world.spawn((at(10.0, 0.0),));
+        let child = s.world.spawn((at(12.0, 3.0),));
+        set_parent(&mut s.world, child, parent).unwrap();
+        propagate_transforms(&mut s);
+        assert_eq!(position(&s, child), Vec2::new(12.0, 3.0));
+        assert_eq!(set_parent(&mut s.world, parent, child), Err(HierarchyError::ParentCycle { entity: parent }));
+        assert_eq!(set_parent(&mut s.world, child, child), Err(HierarchyError::ParentCycle { entity: child }));
+    }
+
+    #[test]
+    fn set_parent_onto_an_existing_cycle_fails() {
+        let mut s = TestState::empty();
+        let a = s.world.spawn((at(0.0, 0.0),));
+        let b = s.world.spawn((at(0.0, 0.0), Parent { parent: a, local_transform: at(0.0, 0.0) }));
+        s.world.insert_one(a, Parent { parent: b, local_transform: at(0.0, 0.0) }).unwrap();
+        let outsider = s.world.spawn((at(0.0, 0.0),));
+        assert_eq!(set_parent(&mut s.world, outsider, a), Err(HierarchyError::ParentCycle { entity: outsider }));
+    }
+
+    #[test]
+    fn despawn_recursive_despawns_descendants() {
+        let mut s = TestState::empty();
+        let root = s.world.spawn((at(0.0, 0.0),));
+        let child = s.world.spawn((at(0.0, 0.0),));
+        let grandchild = s.world.spawn((at(0.0, 0.0),));
+        let other = s.world.spawn((at(0.0, 0.0),));
+        set_parent(&mut s.world, child, root).unwrap();
+        set_parent(&mut s.world, grandchild, child).unwrap();
+        assert_eq!(descendants(&s.world, root), vec![child, grandchild]);
+        despawn_recursive(&mut s.world, root);
+        assert_eq!(s.world.len(), 1);
+        assert!(s.world.contains(other));
+    }
+}
@@ -0,0 +1,341 @@
+//! The profiler times every system the schedule runs. It can periodically log the
+//! average time per system, and it can record every run so that the timings can be
+//! exported as a Chrome trace (open with chrome://tracing or https://ui.perfetto.dev)
+//! or as CSV, along with min/max/p99 statistics per system.
+
+use std::{collections::HashMap, fmt::Write};
+
+/// the accumulated timings of a single system since the last debug log
+struct ProfiledSystem {
+    name: &'static str,
+    time: f64,
+    skipped: usize,
+}
+
+/// a single run of a system (or a whole frame) that was recorded
+#[derive(Clone, Copy, Debug)]
+pub struct ProfiledRun {
+    pub name: &'static str,
+    pub frame: usize,
+    /// milliseconds since the recording started
+    pub start_ms: f64,
+    pub duration_ms: f64,
+}
+
+/// statistics of every recorded run of a single system, in milliseconds
+#[derive(Clone, Copy, Debug)]
+pub struct SystemStats {
+    pub name: &'static str,
+    pub runs: usize,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub mean_ms: f64,
+    pub p99_ms: f64,
+}
+
+/// the name of the recorded run that spans a whole frame
+pub const FRAME_RUN_NAME: &str = "frame";
+
+struct Recording {
+    /// how many more frames to record before stopping
+    frames_left: usize,
+    frame: usize,
+    started_at: f64,
+    frame_started_at: f64,
+    runs: Vec<ProfiledRun>,
+}
+
+/// accumulates the time each system takes, and every N frames
+/// logs the average time per system, as well as how often each system was skipped.
+/// systems are tracked by name, so systems that share a name are combined.
+/// while recording, every run is also kept, see: `Profiler::start_recording`
+pub struct Profiler {
+    frames_before_debug: usize,
+    frame_count: usize,
+    systems: Vec<ProfiledSystem>,
+    indices: HashMap<&'static str, usize>,
+    recording: Option<Recording>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Profiler {
+    /// if frames_before_debug is 0, no averages are logged.
+    pub fn new(frames_before_debug: usize) -> Self {
+        Self {
+            frames_before_debug,
+            frame_count: 0,
+            systems: vec![],
+            indices: HashMap::new(),
+            recording: None,
+        }
+    }
+    /// if set to 0, no averages are logged.
+    pub fn set_frames_before_debug(&mut self, frames_before_debug: usize) {
+        self.frames_before_debug = frames_before_debug;
+        self.frame_count = 0;
+    }
+    /// start keeping every system run for the next N frames.
+    /// any previously recorded runs are discarded.
+    pub fn start_recording(&mut self, frames: usize) {
+        let now = macroquad::miniquad::date::now();
+        self.recording = Some(Recording {
+            frames_left: frames,
+            frame: 0,
+            started_at: now,
+            frame_started_at: now,
+            runs: vec![],
+        });
+    }
+    /// returns true while frames are still being recorded
+    pub fn is_recording(&self) -> bool {
+        self.recording.as_ref().map(|r| r.frames_left > 0).unwrap_or(false)
+    }
+    /// every run recorded so far, in the order they finished
+    pub fn recorded_runs(&self) -> &[ProfiledRun] {
+        match &self.recording {
+            Some(r) => &r.runs,
+            None => &[],
+        }
+    }
+
+    fn get(&mut self, name: &'static str) -> &mut ProfiledSystem {
+        let systems = &mut self.systems;
+        let index = *self.indices.entry(name).or_insert_with(|| {
+            systems.push(ProfiledSystem { name, time: 0.0, skipped: 0 });
+            systems.len() - 1
+        });
+        &mut self.systems[index]
+    }
+    /// start and end are in seconds, as returned by `miniquad::date::now`
+    pub fn record(&mut self, name: &'static str, start: f64, end: f64) {
+        if let Some(recording) = &mut self.recording {
+            if recording.frames_left > 0 {
+                recording.runs.push(ProfiledRun {
+                    name,
+                    frame: recording.frame,
+                    start_ms: (start - recording.started_at) * 1000.0,
+                    duration_ms: (end - start) * 1000.0,
+                });
+            }
+        }
+        if self.frames_before_debug == 0 {
+            return;
+        }
+        self.get(name).time += (end - start) * 1000.0;
+    }
+    pub fn record_skipped(&mut self, name: &'static str) {
+        if self.frames_before_debug == 0 {
+            return;
+        }
+        self.get(name).skipped += 1;
+    }
+    /// marks the start of a frame, so that the time the whole frame takes can be recorded.
+    pub fn begin_frame(&mut self) {
+        if let Some(recording) = &mut self.recording {
+            recording.frame_started_at = macroquad::miniquad::date::now();
+        }
+    }
+    /// records the time the whole frame took, and periodically logs the average time per system.
+    pub fn end_frame(&mut self) {
+        if let Some(recording) = &mut self.recording {
+            if recording.frames_left > 0 {
+                let now = macroquad::miniquad::date::now();
+                recording.runs.push(ProfiledRun {
+                    name: FRAME_RUN_NAME,
+                    frame: recording.frame,
+                    start_ms: (recording.frame_started_at - recording.started_at) * 1000.0,
+                    duration_ms: (now - recording.frame_started_at) * 1000.0,
+                });
+                recording.frame += 1;
+                recording.frames_left -= 1;
+            }
+        }
+        if self.frames_before_debug == 0 {
+            return;
+        }
+        self.frame_count += 1;
+        if self.frame_count < self.frames_before_debug {
+            return;
+        }
+        let frames_before_debug_f64 = self.frames_before_debug as f64;
+        let total_time = self.systems.iter().map(|s| s.time).sum::<f64>() / frames_before_debug_f64;
+        let longest_name = self.systems.iter().map(|s| s.name.len()).max().unwrap_or(0);
+        let mut debug_timings_sorted: Vec<_> = self.systems.iter().collect();
+        debug_timings_sorted.sort_by(|a, b| b.time.partial_cmp(&a.time).unwrap());
+        for system in debug_timings_sorted {
+            let sys_name = system.name;
+            let padding = " ".repeat(longest_name - sys_name.len());
+            let skipped = system.skipped;
+            if system.time < 0.001 {
+                // still show systems that didnt take any time because they were skipped
+                if skipped > 0 {
+                    macroquad::logging::warn!("{}{} skipped {}x", sys_name, padding, skipped);
+                }
+                continue;
+            }
+            let avg_time = system.time / frames_before_debug_f64;
+            let percent = (avg_time / total_time) * 100.0;
+            // 25 bars max, so we divide by 4.0
+            let num_bars = (percent / 4.0).max(1.0);
+
+            let bars = "\u{2588}".repeat(num_bars as usize);
+            if skipped > 0 {
+                macroquad::logging::warn!("{}{} {:0.4}ms {} (skipped {}x)", sys_name, padding, avg_time, bars, skipped);
+            } else {
+                macroquad::logging::warn!("{}{} {:0.4}ms {}", sys_name, padding, avg_time, bars);
+            }
+        }
+        macroquad::logging::warn!("");
+        self.systems.iter_mut().for_each(|s| {
+            s.time = 0.0;
+            s.skipped = 0;
+        });
+        self.frame_count = 0;
+    }
+
+    /// min/max/mean/p99 of every recorded system, and of the whole frame (see: `FRAME_RUN_NAME`),
+    /// sorted by p99, slowest first.
+    pub fn stats(&self) -> Vec<SystemStats> {
+        let mut durations: Vec<(&'static str, Vec<f64>)> = vec![];
+        for run in self.recorded_runs() {
+            match durations.iter_mut().find(|(name, _)| *name == run.name) {
+                Some((_, d)) => d.push(run.duration_ms),
+                None => durations.push((run.name, vec![run.duration_ms])),
+            }
+        }
+        let mut stats: Vec<_> = durations.into_iter().map(|(name, mut d)| {
+            d.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            // nearest rank percentile
+            let p99_index = ((d.len() as f64 * 0.99).ceil() as usize).saturating_sub(1);
+            SystemStats {
+                name,
+                runs: d.len(),
+                min_ms: d[0],
+                max_ms: d[d.len() - 1],
+                mean_ms: d.iter().sum::<f64>() / d.len() as f64,
+                p99_ms: d[p99_index],
+            }
+        }).collect();
+        stats.sort_by(|a, b| b.p99_ms.partial_cmp(&a.p99_ms).unwrap());
+        stats
+    }
+
+    /// the recorded runs in the Chrome trace event format.
+    /// the per system stats are included under the `systemStats` key.
+    pub fn to_chrome_trace(&self) -> String {
+        let mut out = String::from("{\"traceEvents\":[\n");
+        let runs = self.recorded_runs();
+        for (i, run) in runs.iter().enumerate() {
+            // trace event timestamps are in microseconds
+            let _ = write!(
+                out,
+                "{{\"name\":\"{}\",\"cat\":\"system\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":1,\"args\":{{\"frame\":{}}}}}",
+                escape_json(run.name), run.start_ms * 1000.0, run.duration_ms * 1000.0, run.frame,
+            );
+            out.push_str(if i + 1 < runs.len() { ",\n" } else { "\n" });
+        }
+        out.push_str("],\n\"displayTimeUnit\":\"ms\",\n\"systemStats\":[\n");
+        let stats = self.stats();
+        for (i, s) in stats.iter().enumerate() {
+            let _ = write!(
+                out,
+                "{{\"name\":\"{}\",\"runs\":{},\"min_ms\":{:.4},\"max_ms\":{:.4},\"mean_ms\":{:.4},\"p99_ms\":{:.4}}}",
+                escape_json(s.name), s.runs, s.min_ms, s.max_ms, s.mean_ms, s.p99_ms,
+            );
+            out.push_str(if i + 1 < stats.len() { ",\n" } else { "\n" });
+        }
+        out.push_str("]}\n");
+        out
+    }
+    /// every recorded run as a row of: frame,system,start_ms,duration_ms
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("frame,system,start_ms,duration_ms\n");
+        for run in self.recorded_runs() {
+            let _ = writeln!(out, "{},{},{:.4},{:.4}", run.frame, csv_field(run.name), run.start_ms, run.duration_ms);
+        }
+        out
+    }
+    /// the output of `stats` as a row per system of: system,runs,min_ms,max_ms,mean_ms,p99_ms
+    pub fn stats_to_csv(&self) -> String {
+        let mut out = String::from("system,runs,min_ms,max_ms,mean_ms,p99_ms\n");
+        for s in self.stats() {
+            let _ = writeln!(out, "{},{},{:.4},{:.4},{:.4},{:.4}", csv_field(s.name), s.runs, s.min_ms, s.max_ms, s.mean_ms, s.p99_ms);
+        }
+        out
+    }
+    /// writes `<path>.json` (chrome trace), `<path>.csv` (every run)
+    /// and `<path>_stats.csv` (per system stats).
+    pub fn export(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(format!("{}.json", path), self.to_chrome_trace())?;
+        std::fs::write(format!("{}.csv", path), self.to_csv())?;
+        std::fs::write(format!("{}_stats.csv", path), self.stats_to_csv())?;
+        Ok(())
+    }
+}
+
+/// quoted, so that names with commas or quotes (eg: generic paths) stay in their column
+fn csv_field(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+fn escape_json(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(runs: &[(&'static str, f64)]) -> Profiler {
+        Profiler {
+            recording: Some(Recording {
+                frames_left: 0,
+                frame: 0,
+                started_at: 0.0,
+                frame_started_at: 0.0,
+                runs: runs.iter().enumerate().map(|(i, (name, duration_ms))| ProfiledRun {
+                    name,
+                    frame: i,
+                    start_ms: i as f64,
+                    duration_ms: *duration_ms,
+                }).collect(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stats_per_system_slowest_first() {
+        let mut runs: Vec<_> = (1..=100).map(|ms| ("a", ms as f64)).collect();
+        runs.push(("b", 500.0));
+        let stats = recorded(&runs).stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].name, stats[0].runs, stats[0].p99_ms), ("b", 1, 500.0));
+        let a = stats[1];
+        assert_eq!((a.name, a.runs, a.min_ms, a.max_ms, a.mean_ms), ("a", 100, 1.0, 100.0, 50.5));
+        // nearest rank: the 99th of 100 sorted runs
+        assert_eq!(a.p99_ms, 99.0);
+    }
+
+    #[test]
+    fn chrome_trace_has_every_run_and_the_stats() {
+        let trace = recorded(&[("a", 1.0), ("b\"c", 2.0)]).to_chrome_trace();
+        assert!(trace.starts_with("{\"traceEvents\":[\n"));
+        assert!(trace.contains("{\"name\":\"a\",\"cat\":\"system\",\"ph\":\"X\",\"ts\":0.000,\"dur\":1000.000,\"pid\":1,\"tid\":1,\"args\":{\"frame\":0}},\n"));
+        assert!(trace.contains("\"name\":\"b\\\"c\",\"cat\""));
+        assert!(trace.contains("\"systemStats\":[\n{\"name\":\"b\\\"c\",\"runs\":1,"));
+        assert!(trace.ends_with("}\n]}\n"));
+    }
+
+    #[test]
+    fn csv_quotes_system_names() {
+        let csv = recorded(&[("a", 1.0), ("f<A, B>", 2.0)]).to_csv();
+        assert_eq!(csv, "frame,system,start_ms,duration_ms\n0,\"a\",0.0000,1.0000\n1,\"f<A, B>\",1.0000,2.0000\n");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,481 @@
+//! A schedule groups systems into ordered stages. Every frame the stages run
+//! in the order: pre-update, update, post-update, render. Startup systems run
+//! exactly once, before the first frame.
+//! Systems can also be registered to run only in a given app state, see: `Schedule::on_update`.
+
+use std::{any::Any, collections::HashMap};
+
+use crate::{
+    system::{
+        stateless::*,
+        profiler::*,
+    },
+    data::{
+        world::*,
+        loading::*,
+        app_state::*,
+    },
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    /// ran once, before the first frame
+    Startup,
+    PreUpdate,
+    Update,
+    PostUpdate,
+    /// ran once per frame, after all other stages.
+    /// when using a fixed timestep, this is the only stage
+    /// that runs every rendered frame.
+    Render,
+}
+
+impl Stage {
+    /// all stages in the order they are ran
+    pub const ALL: [Stage; 5] = [
+        Stage::Startup,
+        Stage::PreUpdate,
+        Stage::Update,
+        Stage::PostUpdate,
+        Stage::Render,
+    ];
+    /// the stages that make up the simulation. with a fixed timestep
+    /// these are ran zero or more times per frame.
+    pub const SIMULATION: [Stage; 3] = [
+        Stage::PreUpdate,
+        Stage::Update,
+        Stage::PostUpdate,
+    ];
+}
+
+/// the systems to run, grouped by stage. Example:
+/// ```
+/// let schedule = Schedule::new()
+///     .with_system(Stage::Startup, sys!(setup))
+///     .with_system(Stage::PostUpdate, sys!(update_children_transforms))
+///     .with_system(Stage::Render, sys!(handle_pan))
+///     .with_system(Stage::Render, sys!(draw));
+/// ```
+pub struct Schedule<U: UserState<T>, T: TextureEnum> {
+    stages: [Vec<System<U, T>>; 5],
+    app_states: Vec<Box<dyn AnyStateSystems<U, T>>>,
+    fixed_hz: Option<f32>,
+    started: bool,
+    profiler: Profiler,
+    profile_export: Option<(String, usize)>,
+}
+
+impl<U: UserState<T>, T: TextureEnum> Default for Schedule<U, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<U: UserState<T>, T: TextureEnum> Schedule<U, T> {
+    pub fn new() -> Self {
+        Self {
+            stages: Default::default(),
+            app_states: vec![],
+            fixed_hz: None,
+            started: false,
+            profiler: Profiler::new(0),
+            profile_export: None,
+        }
+    }
+    /// adds a system to the end of the given stage
+    pub fn add_system(&mut self, stage: Stage, system: System<U, T>) {
+        self.stages[stage as usize].push(system);
+    }
+    pub fn with_system(mut self, stage: Stage, system: System<U, T>) -> Self {
+        self.add_system(stage, system);
+        self
+    }
+    /// adds all systems to the end of the given stage, in order
+    pub fn with_systems(mut self, stage: Stage, systems: impl IntoIterator<Item = System<U, T>>) -> Self {
+        self.stages[stage as usize].extend(systems);
+        self
+    }
+    pub fn stage(&self, stage: Stage) -> &[System<U, T>] {
+        &self.stages[stage as usize]
+    }
+    /// runs the simulation stages (see: `Stage::SIMULATION`) at a fixed rate,
+    /// zero or more times per frame. they always receive `State::fixed.step` as their delta time.
+    /// the render stage still runs once per rendered frame and receives the real frame time.
+    /// render systems can read `State::fixed.alpha` to interpolate between the
+    /// previous and the next simulation step. panics if hz is not positive and finite.
+    pub fn with_fixed_timestep(mut self, hz: f32) -> Self {
+        // panics here rather than on the first frame
+        FixedTimestep::from_hz(hz);
+        self.fixed_hz = Some(hz);
+        self
+    }
+    /// how many frames to average debug timings over. if set to 0,
+    /// no debug timings are emitted.
+    pub fn set_debug_frame_count(&mut self, debug_frame_count: usize) {
+        self.profiler.set_frames_before_debug(debug_frame_count);
+    }
+    /// records the timings of every system for the first N frames, and then writes them to
+    /// `<path>.json` (chrome trace), `<path>.csv` (every run) and `<path>_stats.csv` (min/max/p99 per system).
+    pub fn with_profile_export(mut self, path: &str, frames: usize) -> Self {
+        self.profile_export = Some((path.to_string(), frames));
+        self
+    }
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+    /// can be used to start recording at any time, see: `Profiler::start_recording`
+    pub fn profiler_mut(&mut self) -> &mut Profiler {
+        &mut self.profiler
+    }
+
+    /// registers an app state enum, and the state to start in. Example:
+    /// ```
+    /// #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+    /// enum Phase { GeneratingTerrain, TilingRegions, Playing }
+    ///
+    /// let schedule = Schedule::new()
+    ///     .with_app_state(Phase::GeneratingTerrain)
+    ///     .on_update(Phase::GeneratingTerrain, sys!(fill_generated_map))
+    ///     .on_enter(Phase::Playing, sys!(spawn_units));
+    /// ```
+    /// the current state is stored in the `AppState<S>` resource,
+    /// and can be changed by systems with `State::set_app_state`.
+    /// on every simulation step, before the pre-update stage, a pending transition is applied:
+    /// the on_exit systems of the previous state run, followed by the on_enter systems of the next state.
+    /// on_update systems run after the update stage.
+    pub fn with_app_state<S: AppStateEnum>(mut self, initial: S) -> Self {
+        self.state_systems::<S>().initial = Some(initial);
+        self
+    }
+    /// runs the system once every time the app state is entered
+    pub fn on_enter<S: AppStateEnum>(mut self, app_state: S, system: System<U, T>) -> Self {
+        self.state_systems::<S>().on_enter.entry(app_state).or_default().push(system);
+        self
+    }
+    /// runs the system once every time the app state is exited
+    pub fn on_exit<S: AppStateEnum>(mut self, app_state: S, system: System<U, T>) -> Self {
+        self.state_systems::<S>().on_exit.entry(app_state).or_default().push(system);
+        self
+    }
+    /// runs the system every simulation step while in the app state, after the update stage.
+    pub fn on_update<S: AppStateEnum>(mut self, app_state: S, system: System<U, T>) -> Self {
+        self.state_systems::<S>().on_update.entry(app_state).or_default().push(system);
+        self
+    }
+    fn state_systems<S: AppStateEnum>(&mut self) -> &mut StateSystems<U, T, S> {
+        let existing = self.app_states.iter_mut()
+            .position(|set| set.as_any_mut().is::<StateSystems<U, T, S>>());
+        let index = match existing {
+            Some(i) => i,
+            None => {
+                self.app_states.push(Box::new(StateSystems::<U, T, S>::default()));
+                self.app_states.len() - 1
+            }
+        };
+        self.app_states[index].as_any_mut().downcast_mut()
+            .expect("position was found by checking the type")
+    }
+
+    /// runs a single frame. on the first call, the startup systems are ran first.
+    /// the simulation stages are affected by `State::time` (pausing, stepping, scaling),
+    /// while the render stage always runs once with the real frame time.
+    /// at the end of the frame, every event queue is advanced by a frame, see: `Events::iter`.
+    pub fn run_frame(&mut self, state: &mut State<U, T>, delta_time: f32) {
+        self.profiler.begin_frame();
+        if !self.started {
+            self.started = true;
+            if let Some((_, frames)) = &self.profile_export {
+                self.profiler.start_recording(*frames);
+            }
+            if let Some(hz) = self.fixed_hz {
+                state.fixed = FixedTimestep::from_hz(hz);
+            }
+            for set in self.app_states.iter_mut() {
+                set.insert_initial(state);
+            }
+            run_systems(&mut self.stages[Stage::Startup as usize], state, delta_time, &mut self.profiler);
+        }
+
+        let simulation_dt = state.time.begin_frame(delta_time);
+        let requested_steps = state.time.take_requested_steps();
+        if self.fixed_hz.is_some() {
+            // while paused the accumulator is left alone, and only the requested steps are ran
+            let steps = if state.time.paused {
+                requested_steps
+            } else {
+                state.fixed.advance(simulation_dt)
+            };
+            let step_dt = state.fixed.step;
+            for _ in 0..steps {
+                self.run_simulation(state, step_dt);
+            }
+        } else if state.time.paused {
+            let step_dt = delta_time * state.time.scale;
+            for _ in 0..requested_steps {
+                self.run_simulation(state, step_dt);
+            }
+        } else {
+            self.run_simulation(state, simulation_dt);
+        }
+        run_systems(&mut self.stages[Stage::Render as usize], state, delta_time, &mut self.profiler);
+        state.events.update();
+        self.profiler.end_frame();
+        self.export_profile_if_done();
+    }
+
+    fn export_profile_if_done(&mut self) {
+        if self.profiler.is_recording() {
+            return;
+        }
+        let path = match self.profile_export.take() {
+            Some((path, _)) => path,
+            None => return,
+        };
+        match self.profiler.export(&path) {
+            Ok(_) => macroquad::logging::warn!("wrote profile to {}.json", path),
+            Err(e) => macroquad::logging::warn!("failed to write profile to {}: {}", path, e),
+        }
+        for s in self.profiler.stats() {
+            macroquad::logging::warn!(
+                "{} runs: {} min: {:0.4}ms max: {:0.4}ms mean: {:0.4}ms p99: {:0.4}ms",
+                s.name, s.runs, s.min_ms, s.max_ms, s.mean_ms, s.p99_ms,
+            );
+        }
+    }
+
+    /// applies app state transitions, and runs the simulation stages
+    /// along with the on_update systems of the current app states.
+    fn run_simulation(&mut self, state: &mut State<U, T>, delta_time: f32) {
+        state.time.elapsed += delta_time as f64;
+        state.events.begin_step();
+        for set in self.app_states.iter_mut() {
+            set.transition(state, delta_time, &mut self.profiler);
+        }
+        run_systems(&mut self.stages[Stage::PreUpdate as usize], state, delta_time, &mut self.profiler);
+        run_systems(&mut self.stages[Stage::Update as usize], state, delta_time, &mut self.profiler);
+        for set in self.app_states.iter_mut() {
+            set.update(state, delta_time, &mut self.profiler);
+        }
+        run_systems(&mut self.stages[Stage::PostUpdate as usize], state, delta_time, &mut self.profiler);
+        state.events.end_step();
+    }
+}
+
+/// runs each system once in order, recording their timings in the profiler.
+/// systems whose run condition does not hold are skipped.
+fn run_systems<U: UserState<T>, T: TextureEnum>(
+    systems: &mut [System<U, T>],
+    state: &mut State<U, T>,
+    delta_time: f32,
+    profiler: &mut Profiler,
+) {
+    for system in systems.iter_mut() {
+        if !system.should_run(state, delta_time) {
+            profiler.record_skipped(system.name);
+            continue;
+        }
+        // not using macroquad::time::get_time, as it requires a graphics context
+        let start = macroquad::miniquad::date::now();
+        system.run(state, delta_time);
+        let end = macroquad::miniquad::date::now();
+        profiler.record(system.name, start, end);
+    }
+}
+
+/// the systems registered for every variant of a single app state enum.
+struct StateSystems<U: UserState<T>, T: TextureEnum, S: AppStateEnum> {
+    initial: Option<S>,
+    on_enter: HashMap<S, Vec<System<U, T>>>,
+    on_exit: HashMap<S, Vec<System<U, T>>>,
+    on_update: HashMap<S, Vec<System<U, T>>>,
+}
+impl<U: UserState<T>, T: TextureEnum, S: AppStateEnum> Default for StateSystems<U, T, S> {
+    fn default() -> Self {
+        Self {
+            initial: None,
+            on_enter: Default::default(),
+            on_exit: Default::default(),
+            on_update: Default::default(),
+        }
+    }
+}
+
+/// type erased interface so the schedule can hold the systems
+/// of multiple app state enums without knowing their types.
+trait AnyStateSystems<U: UserState<T>, T: TextureEnum> {
+    /// inserts the `AppState<S>` resource, unless the user already inserted one.
+    fn insert_initial(&mut self, state: &mut State<U, T>);
+    /// applies a pending transition, if any, running the on_exit and on_enter systems.
+    fn transition(&mut self, state: &mut State<U, T>, delta_time: f32, profiler: &mut Profiler);
+    /// runs the on_update systems of the current state.
+    fn update(&mut self, state: &mut State<U, T>, delta_time: f32, profiler: &mut Profiler);
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<U: UserState<T>, T: TextureEnum, S: AppStateEnum> AnyStateSystems<U, T> for StateSystems<U, T, S> {
+    fn insert_initial(&mut self, state: &mut State<U, T>) {
+        if state.has_resource::<AppState<S>>() {
+            return;
+        }
+        if let Some(initial) = self.initial {
+            state.insert_resource(AppState::new(initial));
+        }
+    }
+    fn transition(&mut self, state: &mut State<U, T>, delta_time: f32, profiler: &mut Profiler) {
+        let transition = match state.get_resource_mut::<AppState<S>>() {
+            Some(app_state) => app_state.apply_transition(),
+            None => return,
+        };
+        let (exited, entered) = match transition {
+            Some(t) => t,
+            None => return,
+        };
+        if let Some(systems) = exited.and_then(|exited| self.on_exit.get_mut(&exited)) {
+            run_systems(systems, state, delta_time, profiler);
+        }
+        if let Some(systems) = self.on_enter.get_mut(&entered) {
+            run_systems(systems, state, delta_time, profiler);
+        }
+    }
+    fn update(&mut self, state: &mut State<U, T>, delta_time: f32, profiler: &mut Profiler) {
+        let current = match state.get_resource::<AppState<S>>() {
+            Some(app_state) => app_state.current(),
+            None => return,
+        };
+        if let Some(systems) = self.on_update.get_mut(&current) {
+            run_systems(systems, state, delta_time, profiler);
+        }
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::{Cell, RefCell}, rc::Rc};
+
+    use super::*;
+    use crate::{data::events::*, sys, testing::*};
+
+    struct Ping;
+
+    #[test]
+    fn simulation_events_survive_frames_without_steps() {
+        let seen = Rc::new(Cell::new(0));
+        let seen_in = seen.clone();
+        let mut reader = EventReader::<Ping>::default();
+        let mut sent = false;
+        let mut schedule = Schedule::<TestUserState, TestTextures>::new()
+            .with_fixed_timestep(10.0)
+            // reads before the event is sent, so only sees it on the next step
+            .with_system(Stage::Update, sys!("read", move |s, _| {
+                seen_in.set(seen_in.get() + s.events.read(&mut reader).count());
+            }))
+            .with_system(Stage::Update, sys!("send", move |s, _| {
+                if !sent {
+                    sent = true;
+                    s.events.send(Ping);
+                }
+            }));
+        let mut s = TestState::empty();
+        schedule.run_frame(&mut s, 0.1);
+        // a frame without any simulation step
+        schedule.run_frame(&mut s, 0.01);
+        assert_eq!(seen.get(), 0);
+        schedule.run_frame(&mut s, 0.1);
+        assert_eq!(seen.get(), 1);
+        // dropped once both the next step and the next frame ended
+        schedule.run_frame(&mut s, 0.1);
+        assert!(s.events.iter::<Ping>().next().is_none());
+    }
+
+    #[test]
+    fn simulation_events_are_kept_while_paused() {
+        let mut sent = false;
+        let mut schedule = Schedule::<TestUserState, TestTextures>::new()
+            .with_system(Stage::Update, sys!("send", move |s, _| {
+                if !sent {
+                    sent = true;
+                    s.events.send(Ping);
+                }
+            }));
+        let mut s = TestState::empty();
+        schedule.run_frame(&mut s, 0.1);
+        s.time.pause();
+        for _ in 0..5 {
+            schedule.run_frame(&mut s, 0.1);
+            assert_eq!(s.events.iter::<Ping>().count(), 1);
+        }
+        // cleared once the next step ended
+        s.time.step();
+        schedule.run_frame(&mut s, 0.1);
+        assert_eq!(s.events.iter::<Ping>().count(), 0);
+    }
+
+    #[test]
+    fn render_events_last_until_the_end_of_the_next_frame() {
+        let mut s = TestState::empty();
+        s.events.send(Ping);
+        s.events.update();
+        assert_eq!(s.events.iter::<Ping>().count(), 1);
+        s.events.update();
+        assert_eq!(s.events.iter::<Ping>().count(), 0);
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+    enum Phase {
+        Loading,
+        Playing,
+    }
+
+    #[test]
+    fn app_state_transitions_run_exit_then_enter() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let logger = |name: &'static str| {
+            let log = log.clone();
+            System::new(move |_: &mut TestState, _| log.borrow_mut().push(name), name)
+        };
+        let mut schedule = Schedule::<TestUserState, TestTextures>::new()
+            .with_app_state(Phase::Loading)
+            .with_system(Stage::Update, logger("update"))
+            .on_enter(Phase::Loading, logger("enter loading"))
+            .on_exit(Phase::Loading, logger("exit loading"))
+            .on_enter(Phase::Playing, logger("enter playing"))
+            .on_update(Phase::Loading, logger("update loading"))
+            .on_update(Phase::Loading, sys!("finish loading", |s, _| s.set_app_state(Phase::Playing)));
+        let mut s = TestState::empty();
+        schedule.run_frame(&mut s, 0.1);
+        // the transition is applied at the start of the next step
+        assert_eq!(s.app_state::<Phase>(), Phase::Loading);
+        schedule.run_frame(&mut s, 0.1);
+        assert_eq!(s.app_state::<Phase>(), Phase::Playing);
+        assert_eq!(*log.borrow(), vec![
+            "enter loading", "update", "update loading",
+            "exit loading", "enter playing", "update",
+        ]);
+    }
+
+    #[test]
+    fn pause_step_and_time_scale() {
+        let simulated = Rc::new(RefCell::new(vec![]));
+        let simulated_in = simulated.clone();
+        let rendered = Rc::new(Cell::new(0));
+        let rendered_in = rendered.clone();
+        let mut schedule = Schedule::<TestUserState, TestTextures>::new()
+            .with_system(Stage::Update, sys!("simulate", move |_, dt| simulated_in.borrow_mut().push(dt)))
+            .with_system(Stage::Render, sys!("render", move |_, _| rendered_in.set(rendered_in.get() + 1)));
+        let mut s = TestState::empty();
+        s.time.scale = 0.5;
+        schedule.run_frame(&mut s, 0.1);
+        s.time.pause();
+        schedule.run_frame(&mut s, 0.1);
+        s.time.step();
+        schedule.run_frame(&mut s, 0.1);
+        schedule.run_frame(&mut s, 0.1);
+        // the render stage runs every frame, even while paused
+        assert_eq!(rendered.get(), 4);
+        assert_eq!(*simulated.borrow(), vec![0.05, 0.05]);
+        assert!((s.time.elapsed - 0.1).abs() < 1e-6);
+    }
+}
@@ -7,22 +7,63 @@ use std::collections::{HashSet, HashMap};
 use bracket_noise::prelude::*;
 use macroquad::prelude::*;
 
+/// the camera: converts between world and screen coordinates.
+/// the zoom and pan settings are per instance, and can be set with the builder methods, eg:
+/// ```
+/// let coords = CoordTransform::default()
+///     .with_zoom_limits(0.5, 40.0)
+///     .with_zoom_speed(0.1)
+///     .with_pan_speed(20.0);
+/// ```
+/// or loaded from a settings file, see: `load_settings`
+#[derive(Clone)]
 pub struct CoordTransform {
+    /// the world point at the top left of the screen when the view is not rotated.
+    /// when rotated, the view rotates around this point. see: `view_matrix`
     pub pan_x: f32,
     pub pan_y: f32,
     pub scale: f32,
+    /// rotation of the view in radians. positive values rotate the world clockwise on screen.
+    pub angle: f32,
 
     pub start_pan_x: f32,
     pub start_pan_y: f32,
 
+    /// how far the pan actions move the view per frame, in screen pixels
     pub wasd_pan_by: f32,
+    /// multiplier of how far the view moves when dragging the mouse. 1.0 keeps the cursor on the same world point
+    pub drag_pan_sensitivity: f32,
 
-    pub pan_keys_left: Vec<KeyCode>,
-    pub pan_keys_right: Vec<KeyCode>,
-    pub pan_keys_up: Vec<KeyCode>,
-    pub pan_keys_down: Vec<KeyCode>,
-    pub pan_mouse: Vec<MouseButton>,
+    /// the keys and buttons that pan and zoom are bound via `State::actions`.
+    /// if false, the mouse wheel bindings of the zoom actions are ignored, other bindings still zoom.
     pub zoom_scroll_enabled: bool,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// how much the scale changes per zoom action, eg: 0.05 zooms by 5%
+    pub zoom_speed: f32,
+    /// how quickly the scale approaches the target scale, per second.
+    /// higher is faster, 0.0 zooms instantly. see: `zoom_to`
+    pub zoom_smoothing: f32,
+    /// the scale being animated towards, if any
+    pub target_scale: Option<f32>,
+    /// the screen point that stays in place while animating the zoom
+    pub zoom_anchor: Vec2,
+    /// if set, the view can not be panned or zoomed out beyond this world space rect.
+    /// if the view is larger than the bounds along an axis, the bounds are centered instead.
+    pub bounds: Option<Rect>,
+    /// the size of the screen area the view is drawn to.
+    /// for `State::coords`, `State::step` sets this to `State::input.screen_size` every frame, if known.
+    pub viewport_size: Vec2,
+    /// set by `handle_pan` if the user panned the view this frame,
+    /// so that automatic camera movement can give control to the user. see: `CameraFollow`
+    pub panned_by_user: bool,
+    /// screen space offset added on top of the pan, eg: by `ScreenShake`.
+    /// kept separate from the pan, so that it doesnt accumulate.
+    pub shake_offset: Vec2,
+    /// how fast the rotate actions rotate the view, in radians per second
+    pub rotation_speed: f32,
+    /// if true, the rotate actions rotate around the cursor, otherwise around the screen center
+    pub rotate_around_cursor: bool,
 }
 impl Default for CoordTransform {
     fn default() -> Self {
@@ -30,50 +71,319 @@ impl Default for CoordTransform {
             pan_x: Default::default(),
             pan_y: Default::default(),
             scale: 1.0,
+            angle: 0.0,
             start_pan_x: Default::default(),
             start_pan_y: Default::default(),
-            // TODO: make user editable setting (pan sensitivity)
             wasd_pan_by: 10.0,
-            pan_keys_left: vec![KeyCode::Left],
-            pan_keys_right: vec![KeyCode::Right],
-            pan_keys_up: vec![KeyCode::Up],
-            pan_keys_down: vec![KeyCode::Down],
-            pan_mouse: vec![MouseButton::Left],
+            drag_pan_sensitivity: 1.0,
             zoom_scroll_enabled: true,
+            min_scale: Self::MIN_SCALE,
+            max_scale: Self::MAX_SCALE,
+            zoom_speed: Self::SCALE_BY,
+            zoom_smoothing: 15.0,
+            target_scale: None,
+            zoom_anchor: Vec2::ZERO,
+            bounds: None,
+            viewport_size: Vec2::ZERO,
+            panned_by_user: false,
+            shake_offset: Vec2::ZERO,
+            rotation_speed: std::f32::consts::FRAC_PI_2,
+            rotate_around_cursor: false,
         }
     }
 }
 
 impl CoordTransform {
+    /// default of `max_scale`
     pub const MAX_SCALE: f32 = 10.0;
+    /// default of `min_scale`
     pub const MIN_SCALE: f32 = 0.1;
+    /// default of `zoom_speed`
     pub const SCALE_BY: f32 = 0.05;
 
-    pub fn to_screen(&self, x: f32, y: f32) -> (f32, f32) {
-        (
-            (x - self.pan_x) * self.scale,
-            (y - self.pan_y) * self.scale,
+    /// the limits are swapped if min_scale is larger, and ignored unless both are positive and finite
+    pub fn with_zoom_limits(mut self, min_scale: f32, max_scale: f32) -> Self {
+        if !valid_zoom_limit(min_scale) || !valid_zoom_limit(max_scale) {
+            return self;
+        }
+        self.min_scale = min_scale.min(max_scale);
+        self.max_scale = min_scale.max(max_scale);
+        self.scale = self.clamp_scale(self.scale);
+        self
+    }
+    pub fn with_zoom_speed(mut self, zoom_speed: f32) -> Self {
+        self.zoom_speed = zoom_speed;
+        self
+    }
+    /// 0.0 zooms instantly
+    pub fn with_zoom_smoothing(mut self, zoom_smoothing: f32) -> Self {
+        self.zoom_smoothing = zoom_smoothing;
+        self
+    }
+    pub fn with_pan_speed(mut self, wasd_pan_by: f32) -> Self {
+        self.wasd_pan_by = wasd_pan_by;
+        self
+    }
+    pub fn with_drag_pan_sensitivity(mut self, drag_pan_sensitivity: f32) -> Self {
+        self.drag_pan_sensitivity = drag_pan_sensitivity;
+        self
+    }
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = self.clamp_scale(scale);
+        self
+    }
+    pub fn with_angle(mut self, angle: f32) -> Self {
+        self.angle = angle;
+        self
+    }
+    pub fn with_rotation_speed(mut self, rotation_speed: f32, rotate_around_cursor: bool) -> Self {
+        self.rotation_speed = rotation_speed;
+        self.rotate_around_cursor = rotate_around_cursor;
+        self
+    }
+    pub fn with_bounds(mut self, bounds: Rect) -> Self {
+        self.bounds = Some(bounds);
+        self.clamp_to_bounds();
+        self
+    }
+    pub fn with_viewport_size(mut self, viewport_size: Vec2) -> Self {
+        self.viewport_size = viewport_size;
+        self
+    }
+
+    /// the viewport size. for `State::coords` this is the screen size of the current frame,
+    /// see: `State::step`. zero until it is set, in which case the view is treated as a single point.
+    pub fn screen_size(&self) -> Vec2 {
+        self.viewport_size
+    }
+    /// the smallest scale allowed. with bounds, this is at least
+    /// the scale at which the whole bounds are visible.
+    pub fn effective_min_scale(&self) -> f32 {
+        let bounds = match self.bounds {
+            Some(bounds) if bounds.w > 0.0 && bounds.h > 0.0 => bounds,
+            _ => return self.min_scale,
+        };
+        let size = self.screen_size();
+        let fit_scale = (size.x / bounds.w).min(size.y / bounds.h);
+        self.min_scale.max(fit_scale.min(self.max_scale))
+    }
+    pub fn clamp_scale(&self, scale: f32) -> f32 {
+        // not f32::clamp, which panics if the limits were set to invalid values by hand
+        scale.max(self.effective_min_scale()).min(self.max_scale)
+    }
+    /// the part of the world that is currently visible, ignoring any shake.
+    /// when rotated, this is the axis aligned rect that contains the whole view.
+    pub fn visible_world_rect(&self) -> Rect {
+        let size = self.screen_size();
+        let corners = [Vec2::ZERO, Vec2::new(size.x, 0.0), Vec2::new(0.0, size.y), size]
+            .map(|c| self.to_world_unshaken(c));
+        let min = corners.iter().fold(Vec2::splat(f32::MAX), |a, b| a.min(*b));
+        let max = corners.iter().fold(Vec2::splat(f32::MIN), |a, b| a.max(*b));
+        Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+    /// clamps the scale and pan so that the view stays within the bounds, if any.
+    /// called by `handle_pan` every frame.
+    pub fn clamp_to_bounds(&mut self) {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        self.scale = self.clamp_scale(self.scale);
+        let visible = self.visible_world_rect();
+        self.pan_x += clamp_axis_shift(visible.x, visible.w, bounds.x, bounds.w);
+        self.pan_y += clamp_axis_shift(visible.y, visible.h, bounds.y, bounds.h);
+    }
+    /// zooms and centers the view so that the world space rect is fully visible,
+    /// with at least `padding` screen pixels around it. cancels any zoom animation.
+    pub fn fit_rect(&mut self, rect: Rect, padding: f32) {
+        let size = self.screen_size();
+        let available = (size - Vec2::splat(padding * 2.0)).max(Vec2::ONE);
+        let scale = (available.x / rect.w.max(f32::EPSILON)).min(available.y / rect.h.max(f32::EPSILON));
+        self.target_scale = None;
+        self.scale = self.clamp_scale(scale);
+        let center = rect.center();
+        self.center_to(center.x, center.y);
+    }
+    /// sets the scale immediately, keeping the world point under the screen point in place.
+    /// cancels any zoom animation.
+    pub fn set_scale_around(&mut self, scale: f32, screen_x: f32, screen_y: f32) {
+        self.target_scale = None;
+        self.scale_around(scale, screen_x, screen_y);
+    }
+    fn scale_around(&mut self, scale: f32, screen_x: f32, screen_y: f32) {
+        let world = self.to_world(screen_x, screen_y);
+        self.scale = self.clamp_scale(scale);
+        self.pin(world.into(), Vec2::new(screen_x, screen_y) - self.shake_offset);
+        self.clamp_to_bounds();
+    }
+    /// sets the angle, keeping the world point under the screen point in place
+    pub fn set_angle_around(&mut self, angle: f32, screen_x: f32, screen_y: f32) {
+        let world = self.to_world(screen_x, screen_y);
+        self.angle = angle;
+        self.pin(world.into(), Vec2::new(screen_x, screen_y) - self.shake_offset);
+        self.clamp_to_bounds();
+    }
+    /// rotates the view by the angle, around the screen point
+    pub fn rotate_around(&mut self, angle: f32, screen_x: f32, screen_y: f32) {
+        self.set_angle_around(self.angle + angle, screen_x, screen_y);
+    }
+    /// sets the pan so that the world point is drawn at the screen point, ignoring any shake
+    fn pin(&mut self, world: Vec2, screen: Vec2) {
+        let pan = world - self.screen_to_world_vector(screen);
+        self.pan_x = pan.x;
+        self.pan_y = pan.y;
+    }
+    /// starts animating the scale towards the target, keeping the world point under
+    /// the screen point in place. the animation advances in `update_zoom`.
+    /// if zoom_smoothing is 0.0, the scale is set immediately.
+    pub fn zoom_to(&mut self, target_scale: f32, screen_x: f32, screen_y: f32) {
+        let target_scale = self.clamp_scale(target_scale);
+        if self.zoom_smoothing <= 0.0 {
+            self.set_scale_around(target_scale, screen_x, screen_y);
+            return;
+        }
+        self.target_scale = Some(target_scale);
+        self.zoom_anchor = Vec2::new(screen_x, screen_y);
+    }
+    /// zooms in (steps > 0) or out (steps < 0) by zoom_speed per step, relative to
+    /// the current target scale, so that zooming repeatedly during an animation accumulates.
+    pub fn zoom_by_steps(&mut self, steps: f32, screen_x: f32, screen_y: f32) {
+        let from = self.target_scale.unwrap_or(self.scale);
+        self.zoom_to(from * (1.0 + self.zoom_speed).powf(steps), screen_x, screen_y);
+    }
+    /// advances the zoom animation, if any. called by `handle_pan`
+    pub fn update_zoom(&mut self, dt: f32) {
+        let target = match self.target_scale {
+            Some(target) => target,
+            None => return,
+        };
+        // exponential smoothing, so that the animation is independent of the frame rate
+        let t = 1.0 - (-self.zoom_smoothing * dt).exp();
+        let mut scale = self.scale + (target - self.scale) * t;
+        if (target - scale).abs() <= target * 0.001 {
+            scale = target;
+            self.target_scale = None;
+        }
+        let anchor = self.zoom_anchor;
+        self.scale_around(scale, anchor.x, anchor.y);
+    }
+
+    /// the per instance settings in the settings file format: a line per setting of `name = value`
+    pub fn save_settings(&self) -> String {
+        format!(
+            "min_scale = {}\nmax_scale = {}\nzoom_speed = {}\nzoom_smoothing = {}\nwasd_pan_by = {}\ndrag_pan_sensitivity = {}\nzoom_scroll_enabled = {}\nrotation_speed = {}\nrotate_around_cursor = {}\n",
+            self.min_scale, self.max_scale, self.zoom_speed, self.zoom_smoothing,
+            self.wasd_pan_by, self.drag_pan_sensitivity, self.zoom_scroll_enabled,
+            self.rotation_speed, self.rotate_around_cursor,
         )
     }
+    /// sets every setting in the file, see: `save_settings`. settings that are not
+    /// in the file are kept. empty lines and lines starting with # are ignored.
+    /// if the file is invalid, eg: has a negative or NaN value, no setting is changed.
+    pub fn load_settings(&mut self, data: &str) -> std::io::Result<()> {
+        let invalid = |line: usize, msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("line {}: {}", line + 1, msg));
+        // parse into a copy first, so that an invalid file doesnt leave the settings half loaded
+        let mut loaded = self.clone();
+        let this = &mut loaded;
+        for (line_index, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once('=')
+                .ok_or_else(|| invalid(line_index, "expected: <setting> = <value>".to_string()))?;
+            let (name, value) = (name.trim(), value.trim());
+            let flag = match name {
+                "zoom_scroll_enabled" => Some(&mut this.zoom_scroll_enabled),
+                "rotate_around_cursor" => Some(&mut this.rotate_around_cursor),
+                _ => None,
+            };
+            if let Some(flag) = flag {
+                *flag = value.parse()
+                    .map_err(|_| invalid(line_index, format!("invalid value {}", value)))?;
+                continue;
+            }
+            let setting = match name {
+                "min_scale" => &mut this.min_scale,
+                "max_scale" => &mut this.max_scale,
+                "zoom_speed" => &mut this.zoom_speed,
+                "zoom_smoothing" => &mut this.zoom_smoothing,
+                "wasd_pan_by" => &mut this.wasd_pan_by,
+                "drag_pan_sensitivity" => &mut this.drag_pan_sensitivity,
+                "rotation_speed" => &mut this.rotation_speed,
+                _ => return Err(invalid(line_index, format!("unknown setting {}", name))),
+            };
+            // every setting is a speed, size or limit, so negative values make no sense
+            *setting = value.parse::<f32>().ok().filter(|v| v.is_finite() && *v >= 0.0)
+                .ok_or_else(|| invalid(line_index, format!("invalid value {}", value)))?;
+        }
+        if !valid_zoom_limit(loaded.min_scale) || !valid_zoom_limit(loaded.max_scale) || loaded.min_scale > loaded.max_scale {
+            let msg = format!("invalid zoom limits: min_scale {} max_scale {}", loaded.min_scale, loaded.max_scale);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg));
+        }
+        loaded.scale = loaded.clamp_scale(loaded.scale);
+        *self = loaded;
+        Ok(())
+    }
+    pub fn save_settings_to_file(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.save_settings())
+    }
+    pub fn load_settings_from_file(&mut self, path: &str) -> std::io::Result<()> {
+        let data = std::fs::read_to_string(path)?;
+        self.load_settings(&data)
+    }
+
+    /// converts world coordinates to screen coordinates:
+    /// moves the pan to the origin, scales, rotates, then adds the shake offset
+    pub fn view_matrix(&self) -> Affine2 {
+        Affine2::from_translation(self.shake_offset)
+            * Affine2::from_angle(self.angle)
+            * Affine2::from_scale(Vec2::splat(self.scale))
+            * Affine2::from_translation(-Vec2::new(self.pan_x, self.pan_y))
+    }
+    pub fn to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        self.view_matrix().transform_point2(Vec2::new(x, y)).into()
+    }
     pub fn to_world(&self, x: f32, y: f32) -> (f32, f32) {
-        (
-            (x / self.scale) + self.pan_x,
-            (y / self.scale) + self.pan_y,
-        )
+        self.to_world_unshaken(Vec2::new(x, y) - self.shake_offset).into()
+    }
+    /// like `to_world`, as if there was no shake. the bounds are kept with this,
+    /// so that the shake doesnt move the view
+    fn to_world_unshaken(&self, screen: Vec2) -> Vec2 {
+        Vec2::new(self.pan_x, self.pan_y) + self.screen_to_world_vector(screen)
+    }
+    /// converts a direction/distance on screen to one in the world, eg: how far to pan
+    /// for the mouse to move by a number of pixels. unlike `to_world`, this ignores the pan.
+    pub fn screen_to_world_vector(&self, v: Vec2) -> Vec2 {
+        Vec2::from_angle(-self.angle).rotate(v) / self.scale
+    }
+    /// the world point at the center of the view, ignoring any shake
+    pub fn view_center(&self) -> Vec2 {
+        Vec2::new(self.pan_x, self.pan_y) + self.screen_to_world_vector(self.screen_size() / 2.0)
     }
     /// Center the view on a given point at the current scale
     pub fn center_to(&mut self, cx: f32, cy: f32) {
-        // Calculate the screen center in world coordinates
-        let (screen_width, screen_height) = (screen_width(), screen_height());
-
-        // Calculate the pan values to center the view on the given point
-        let new_pan_x = cx - screen_width / (2.0 * self.scale);
-        let new_pan_y = cy - screen_height / (2.0 * self.scale);
+        self.center_to_on_screen(cx, cy, self.screen_size());
+        self.clamp_to_bounds();
+    }
+    /// like `center_to`, but for a given screen size instead of the window's size,
+    /// eg: `s.coords.center_to_on_screen(cx, cy, s.input.screen_size)`
+    pub fn center_to_on_screen(&mut self, cx: f32, cy: f32, screen_size: Vec2) {
+        self.pin(Vec2::new(cx, cy), screen_size / 2.0);
+    }
+}
 
-        // Update the internal state with the new pan values
-        self.pan_x = new_pan_x;
-        self.pan_y = new_pan_y;
+/// how far a view of the given size must move along one axis to stay within the bounds.
+/// if the view is larger than the bounds, the view is moved so that the bounds are centered in it.
+fn clamp_axis_shift(view_start: f32, view_size: f32, bounds_start: f32, bounds_size: f32) -> f32 {
+    if view_size >= bounds_size {
+        return bounds_start - (view_size - bounds_size) / 2.0 - view_start;
     }
+    view_start.clamp(bounds_start, bounds_start + bounds_size - view_size) - view_start
+}
+
+fn valid_zoom_limit(scale: f32) -> bool {
+    scale.is_finite() && scale > 0.0
 }
 
 pub struct RandomMapGen {
@@ -150,6 +460,9 @@ impl RandomMapGen {
     }
 }
 
+/// whether or not to reset the animation, and the tiles newly added to the a and b sets
+pub type TilingStep = (bool, Vec<(i32, i32)>, Vec<(i32, i32)>);
+
 #[derive(Default)]
 pub struct RecursiveTiling {
     /// the algorithm will continue to subdivide
@@ -215,10 +528,7 @@ impl RecursiveTiling {
         }
     }
     pub fn get_random_from_frontier(set: &HashSet<(i32, i32)>) -> Option<(i32, i32)> {
-        for i in set.iter() {
-            return Some(*i);
-        }
-        return None
+        set.iter().next().copied()
     }
 
     pub fn get_surrounding(i: impl Into<Option<(i32, i32)>>, include_self: bool) -> Vec<(i32, i32)> {
@@ -238,7 +548,7 @@ impl RecursiveTiling {
         out
     }
     /// calls next N times. returns a vec that contains the output of all N calls.
-    pub fn next_n(&mut self, rng: &mut fastrand::Rng, n: usize) -> (bool, Vec<(i32, i32)>, Vec<(i32, i32)>) {
+    pub fn next_n(&mut self, rng: &mut fastrand::Rng, n: usize) -> TilingStep {
         let mut a_new = vec![];
         let mut b_new = vec![];
         let mut should_reset = false;
@@ -310,7 +620,7 @@ impl RecursiveTiling {
     }
     /// returns 2 vectors and a bool: whether or not to reset the animation,
     /// new tiles inserted into the current a set, and the b set.
-    pub fn next(&mut self, rng: &mut fastrand::Rng) -> (bool, Vec<(i32, i32)>, Vec<(i32, i32)>) {
+    pub fn next(&mut self, rng: &mut fastrand::Rng) -> TilingStep {
         let mut a_new = vec![];
         let mut b_new = vec![];
         let mut should_reset = false;
@@ -644,11 +954,11 @@ impl Astar {
         start: (i32, i32),
         goal: (i32, i32),
     ) -> Self {
-        let mut astar = Astar::default();
-        astar.start = start;
-        astar.goal = goal;
-
-        astar
+        Astar {
+            start,
+            goal,
+            ..Default::default()
+        }
     }
     pub fn set_impassable(&mut self, impassable: HashSet<(i32, i32)>) {
         self.impassable_obstacles = impassable;
@@ -677,18 +987,15 @@ impl Astar {
                         ]
                     },
                 }.into_iter();
-                return surrounding_tiles.filter(|x| {
-                    match self.impassable_obstacles.get(&x) {
-                        Some(_) => false,
-                        None => true,
-                    }
+                surrounding_tiles.filter(|x| {
+                    !self.impassable_obstacles.contains(x)
                 }).map(|x| {
                     let cost = match self.cost_obstacles.get(&x) {
                         Some(c) => *c,
                         None => 1,
                     };
                     (x, cost)
-                }).collect::<Vec<_>>();
+                }).collect::<Vec<_>>()
             },
             |&(x, y)| ((self.goal.0.abs_diff(x) + self.goal.1.abs_diff(y)) / 3) as i32,
             |p| *p == self.goal,
@@ -696,3 +1003,51 @@ impl Astar {
         Some(path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_settings_change_nothing() {
+        let mut coords = CoordTransform::default();
+        for data in ["min_scale = 20", "max_scale = NaN", "zoom_speed = 0.1\nmax_scale = inf", "wasd_pan_by = 5\nnope = 1"] {
+            assert!(coords.load_settings(data).is_err(), "{}", data);
+            assert_eq!(coords.save_settings(), CoordTransform::default().save_settings());
+        }
+        coords.load_settings("min_scale = 0.5\nmax_scale = 2\nwasd_pan_by = 5").unwrap();
+        assert_eq!((coords.min_scale, coords.max_scale, coords.wasd_pan_by), (0.5, 2.0, 5.0));
+    }
+
+    #[test]
+    fn zoom_limits_are_validated() {
+        let coords = CoordTransform::default().with_zoom_limits(4.0, 2.0).with_scale(10.0);
+        assert_eq!((coords.min_scale, coords.max_scale, coords.scale), (2.0, 4.0, 4.0));
+        let coords = CoordTransform::default().with_zoom_limits(f32::NAN, 2.0);
+        assert_eq!((coords.min_scale, coords.max_scale), (CoordTransform::MIN_SCALE, CoordTransform::MAX_SCALE));
+    }
+
+    #[test]
+    fn shaking_at_a_bound_does_not_move_the_pan() {
+        let mut coords = CoordTransform::default()
+            .with_viewport_size(Vec2::new(100.0, 100.0))
+            .with_bounds(Rect::new(0.0, 0.0, 1000.0, 1000.0));
+        for i in 0..10 {
+            coords.shake_offset = Vec2::splat(if i % 2 == 0 { 5.0 } else { -5.0 });
+            coords.clamp_to_bounds();
+            assert_eq!((coords.pan_x, coords.pan_y), (0.0, 0.0));
+        }
+        coords.shake_offset = Vec2::ZERO;
+        coords.clamp_to_bounds();
+        assert_eq!((coords.pan_x, coords.pan_y), (0.0, 0.0));
+    }
+
+    #[test]
+    fn negative_settings_are_invalid() {
+        let mut coords = CoordTransform::default();
+        for data in ["zoom_speed = -0.1", "zoom_smoothing = -1", "wasd_pan_by = -10", "zoom_speed = NaN"] {
+            assert!(coords.load_settings(data).is_err(), "{}", data);
+        }
+        assert_eq!(coords.save_settings(), CoordTransform::default().save_settings());
+    }
+}
@@ -7,38 +7,103 @@ use macroquad::prelude::*;
 
 use crate::{
     components::*,
-    system::stateful::*,
+    system::hierarchy::*,
     data::{
         world::*,
-        loading::*
+        loading::*,
+        actions::*,
     },
 };
 
 
-type SystemFn<U, T> = fn(&mut State<U, T>, f32);
+/// the function that a system runs. this can be a plain function, or a closure
+/// that owns its own local state (timers, scratch buffers, rng, etc.) between runs.
+pub type SystemFn<U, T> = Box<dyn FnMut(&mut State<U, T>, f32)>;
 type SystemName = &'static str;
-/// convenience type alias for your system functions.
-/// The output of the `sys` macro outputs a System<U, T> type.
+/// a predicate that is checked before every run of a system.
+/// if it returns false, the system is skipped. see: `System::run_if`
+pub type RunCondition<U, T> = Box<dyn FnMut(&State<U, T>, f32) -> bool>;
+
+/// a system function, the name of that function (used for profiling),
+/// and optionally a condition that must hold for the system to run.
+/// The `sys` macro outputs a System<U, T> type.
 /// It is recommended to define your own type alias as follows:
 /// ```
 /// pub type MySystem = System<MyUserState, Textures>;
 /// ```
 /// And then you can use this conveniently like so:
 /// ```
-/// fn get_all_systems() -> &'static [MySystem] {
-///     &[
+/// fn get_update_systems() -> [MySystem; 3] {
+///     let mut frames = 0;
+///     [
 ///         sys!(do_stuff),
+///         sys!(do_other_stuff).run_if(every_seconds(1.0)),
+///         // closures can keep local state between runs:
+///         sys!("count_frames", move |_s, _dt| {
+///             frames += 1;
+///         }),
 ///     ]
 /// }
+/// let schedule = Schedule::new().with_systems(Stage::Update, get_update_systems());
 /// ```
-pub type System<U, T> = (SystemFn<U, T>, SystemName);
+pub struct System<U: UserState<T>, T: TextureEnum> {
+    pub f: SystemFn<U, T>,
+    pub name: SystemName,
+    pub condition: Option<RunCondition<U, T>>,
+    /// if true, the system is skipped after it ran once. see: `System::run_once`
+    pub once: bool,
+    has_ran: bool,
+}
+
+impl<U: UserState<T>, T: TextureEnum> System<U, T> {
+    pub fn new(f: impl FnMut(&mut State<U, T>, f32) + 'static, name: SystemName) -> Self {
+        Self { f: Box::new(f), name, condition: None, once: false, has_ran: false }
+    }
+    /// only run this system when the condition returns true.
+    /// if called multiple times, all conditions must return true. like `&&`, the conditions
+    /// are checked in order, and a condition is not checked once an earlier one failed.
+    /// so stateful conditions (eg: `every_seconds`) should come last, to only advance
+    /// when every other condition holds.
+    /// see `system::conditions` for common conditions.
+    pub fn run_if(mut self, mut condition: impl FnMut(&State<U, T>, f32) -> bool + 'static) -> Self {
+        self.condition = match self.condition.take() {
+            Some(mut existing) => Some(Box::new(move |s, dt| existing(s, dt) && condition(s, dt))),
+            None => Some(Box::new(condition)),
+        };
+        self
+    }
+    /// the system runs the first time its run conditions hold, and is skipped from then on
+    pub fn run_once(mut self) -> Self {
+        self.once = true;
+        self
+    }
+    /// checks the run condition, if any.
+    pub fn should_run(&mut self, s: &State<U, T>, dt: f32) -> bool {
+        if self.once && self.has_ran {
+            return false;
+        }
+        match &mut self.condition {
+            Some(condition) => condition(s, dt),
+            None => true,
+        }
+    }
+    pub fn run(&mut self, s: &mut State<U, T>, dt: f32) {
+        self.has_ran = true;
+        (self.f)(s, dt);
+    }
+}
 
-/// helper macro to create a system tuple consisting
-/// of a function, and the stringified name of that function
+/// helper macro to create a system consisting
+/// of a function, and the stringified name of that function.
+/// closures can't be named by stringifying them, so for closures
+/// pass the name first: `sys!("name", |s, dt| { ... })`
 #[macro_export]
 macro_rules! sys {
+    ($name:literal, $f:expr) => {
+        $crate::system::stateless::System::new($f, $name)
+    };
     ($f:expr) => {
-        ($f, stringify!($f))
+        $crate::system::stateless::System::new($f, stringify!($f))
     };
 }
 
@@ -46,142 +111,425 @@ pub fn do_stuff<U: UserState<T>, T: TextureEnum>(_s: &mut State<U, T>, _dt: f32)
 
 }
 
-/// iterates over all children who have parents
-/// and update the children's absolute transform
+/// updates the absolute transform of every child from its parent's transform and its local transform.
+/// transforms are propagated from the roots down, and unchanged transforms are not recomputed.
+/// dangling parents and parent cycles are sent as `HierarchyError` events, see: `propagate_transforms`
 pub fn update_children_transforms<U: UserState<T>, T: TextureEnum>(s: &mut State<U, T>, _dt: f32) {
-    let mut parents = s.world.query::<&Parent>();
-    let parents = parents.view();
-
-    // View of entities that don't have parents, i.e. roots of the transform hierarchy
-    let mut roots = s.world.query::<&Transform>().without::<&Parent>();
-    let roots = roots.view();
-
-    for (_entity, (parent, absolute)) in s.world.query::<(&Parent, &mut Transform)>().iter() {
-        // Walk the hierarchy from this entity to the final entity that doesnt have any parents
-        let mut relative_transform = parent.local_transform;
-        let mut ancestor = parent.parent;
-        while let Some(next) = parents.get(ancestor) {
-            relative_transform.d = next.local_transform.d * relative_transform.d;
-            ancestor = next.parent;
-        }
-        if let Some(ancestor_transform) = roots.get(ancestor) {
-            absolute.d = ancestor_transform.d * relative_transform.d;
-        } // TODO: else log error? how could this happen
-    }
+    propagate_transforms(s);
 }
 
-pub fn handle_pan<U: UserState<T>, T: TextureEnum>(s: &mut State<U, T>, _dt: f32) {
+/// pans with the PanLeft/PanRight/PanUp/PanDown actions or by dragging with the PanDrag action,
+/// zooms around the cursor by a step per press of the ZoomIn/ZoomOut actions, and rotates with the RotateLeft/RotateRight
+/// actions. see: `State::actions`. the zoom is animated towards the target scale, see: `CoordTransform::zoom_smoothing`.
+/// should run in the render stage, so that the view can be moved while the simulation is paused,
+/// and the input is handled exactly once per rendered frame.
+pub fn handle_pan<U: UserState<T>, T: TextureEnum>(s: &mut State<U, T>, dt: f32) {
+    let input = &s.input;
+    let actions = &s.actions;
     let coords = &mut s.coords;
     // always allow panning with keys
     let mut key_panned = false;
-    for key in coords.pan_keys_up.iter() {
-        if is_key_down(*key) {
-            key_panned = true;
-            coords.pan_y -= coords.wasd_pan_by / coords.scale;
-        }
-    }
-    for key in coords.pan_keys_left.iter() {
-        if is_key_down(*key) {
-            key_panned = true;
-            coords.pan_x -= coords.wasd_pan_by / coords.scale;
-        }
-    }
-    for key in coords.pan_keys_down.iter() {
-        if is_key_down(*key) {
-            key_panned = true;
-            coords.pan_y += coords.wasd_pan_by / coords.scale;
-        }
-    }
-    for key in coords.pan_keys_right.iter() {
-        if is_key_down(*key) {
+    let key_pans = [
+        (Action::PanUp, 0.0, -1.0),
+        (Action::PanLeft, -1.0, 0.0),
+        (Action::PanDown, 0.0, 1.0),
+        (Action::PanRight, 1.0, 0.0),
+    ];
+    for (action, dx, dy) in key_pans {
+        if actions.is_down(action, input) {
             key_panned = true;
-            coords.pan_x += coords.wasd_pan_by / coords.scale;
+            // pan along the screen's axes, which differ from the world's axes when rotated
+            let pan_by = coords.screen_to_world_vector(Vec2::new(dx, dy) * coords.wasd_pan_by);
+            coords.pan_x += pan_by.x;
+            coords.pan_y += pan_by.y;
         }
     }
-    let (x, y) = mouse_position();
+    let (x, y) = input.mouse_position();
+    coords.panned_by_user = key_panned;
 
     // prevent double panning if already panned with keys
     let can_pan = !key_panned;
     // handle pan:
     if can_pan {
-        for mbutton in coords.pan_mouse.iter() {
-            if is_mouse_button_pressed(*mbutton) {
-                coords.start_pan_x = x;
-                coords.start_pan_y = y;
-            }
-            if is_mouse_button_down(*mbutton) {
-                coords.pan_x -= (x - coords.start_pan_x) / coords.scale;
-                coords.pan_y -= (y - coords.start_pan_y) / coords.scale;
-                coords.start_pan_x = x;
-                coords.start_pan_y = y;
+        if actions.is_pressed(Action::PanDrag, input) {
+            coords.start_pan_x = x;
+            coords.start_pan_y = y;
+        }
+        if actions.is_down(Action::PanDrag, input) {
+            if x != coords.start_pan_x || y != coords.start_pan_y {
+                coords.panned_by_user = true;
             }
+            let dragged = Vec2::new(x - coords.start_pan_x, y - coords.start_pan_y);
+            let pan_by = coords.screen_to_world_vector(dragged * coords.drag_pan_sensitivity);
+            coords.pan_x -= pan_by.x;
+            coords.pan_y -= pan_by.y;
+            coords.start_pan_x = x;
+            coords.start_pan_y = y;
         }
     }
 
-    if coords.zoom_scroll_enabled {
-        // handle zoom:
-        let (wx_before, wy_before) = coords.to_world(x, y);
-        let (_, scrolly) = mouse_wheel();
-        if scrolly > 0.0 {
-            coords.scale *= 1.0 + CoordTransform::SCALE_BY;
-        }
-        if scrolly < 0.0 {
-            coords.scale *= 1.0 - CoordTransform::SCALE_BY;
-        }
-        if coords.scale < CoordTransform::MIN_SCALE {
-            coords.scale = CoordTransform::MIN_SCALE;
-        }
-        if coords.scale > CoordTransform::MAX_SCALE {
-            coords.scale = CoordTransform::MAX_SCALE;
-        }
-        let (wx_after, wy_after) = coords.to_world(x, y);
-        coords.pan_x += wx_before - wx_after;
-        coords.pan_y += wy_before - wy_after;
+    // handle zoom: a step per press, or per frame the wheel scrolls,
+    // so that holding a key doesnt zoom faster at higher frame rates
+    let zoom_scroll_enabled = coords.zoom_scroll_enabled;
+    let zoom_pressed = |action: Action| actions.bindings(action).iter().any(|binding| match binding {
+        Binding::WheelUp | Binding::WheelDown => zoom_scroll_enabled && binding.is_pressed(input),
+        _ => binding.is_pressed(input),
+    });
+    if zoom_pressed(Action::ZoomIn) {
+        coords.zoom_by_steps(1.0, x, y);
+    }
+    if zoom_pressed(Action::ZoomOut) {
+        coords.zoom_by_steps(-1.0, x, y);
     }
+    coords.update_zoom(dt);
+
+    let mut rotate_by = 0.0;
+    if actions.is_down(Action::RotateLeft, input) {
+        rotate_by -= coords.rotation_speed * dt;
+    }
+    if actions.is_down(Action::RotateRight, input) {
+        rotate_by += coords.rotation_speed * dt;
+    }
+    if rotate_by != 0.0 {
+        let pivot = if coords.rotate_around_cursor {
+            Vec2::new(x, y)
+        } else {
+            coords.screen_size() / 2.0
+        };
+        coords.rotate_around(rotate_by, pivot.x, pivot.y);
+    }
+    coords.clamp_to_bounds();
 }
 
 /// draw requires entities with the following components:
 /// - transform
-/// - drawable
+/// - drawable, triangle or line segment
 /// - layer (0 - 9)
-pub fn draw<U: UserState<T>, T: TextureEnum>(s: &mut State<U, T>, dt: f32) {
-    draw_layer::<_, _, Layer0>(s, dt);
-    draw_layer::<_, _, Layer1>(s, dt);
-    draw_layer::<_, _, Layer2>(s, dt);
-    draw_layer::<_, _, Layer3>(s, dt);
-    draw_layer::<_, _, Layer4>(s, dt);
-    draw_layer::<_, _, Layer5>(s, dt);
-    draw_layer::<_, _, Layer6>(s, dt);
-    draw_layer::<_, _, Layer7>(s, dt);
-    draw_layer::<_, _, Layer8>(s, dt);
-    draw_layer::<_, _, Layer9>(s, dt);
+///
+/// the layers are drawn through `State::coords` to the whole screen (only the layers in
+/// the `MainCameraLayers` resource, if it exists), and then through every `CameraView` entity
+/// to its viewport, see: `CameraView`
+pub fn draw<U: UserState<T>, T: TextureEnum>(s: &mut State<U, T>, _dt: f32) {
+    let main_layers = s.get_resource::<MainCameraLayers>().map(|l| l.0).unwrap_or(LayerMask::ALL);
+    draw_layers(&s.world, s.coords.view_matrix(), main_layers);
+
+    let mut cameras: Vec<_> = s.world.query::<&CameraView>().iter()
+        .map(|(_, camera)| (camera.order, camera.view_matrix(), camera.viewport, camera.layers, camera.clear_color))
+        .collect();
+    cameras.sort_by_key(|(order, ..)| *order);
+    for (_, view, viewport, layers, clear_color) in cameras {
+        // only draw within the viewport
+        let clip = (viewport.x as i32, viewport.y as i32, viewport.w as i32, viewport.h as i32);
+        unsafe { get_internal_gl() }.quad_gl.scissor(Some(clip));
+        if let Some(color) = clear_color {
+            draw_rectangle(viewport.x, viewport.y, viewport.w, viewport.h, color);
+        }
+        draw_layers(&s.world, view, layers);
+        unsafe { get_internal_gl() }.quad_gl.scissor(None);
+    }
+}
+
+/// the layers that `draw` draws through `State::coords`. if this resource doesnt exist, every layer is drawn.
+/// eg: to only show a debug layer in a `CameraView`: `s.insert_resource(MainCameraLayers(LayerMask::ALL.without(9)))`
+pub struct MainCameraLayers(pub LayerMask);
+
+fn draw_layers(world: &World, view: Affine2, layers: LayerMask) {
+    let draw_fns = [
+        draw_layer_with::<Layer0>,
+        draw_layer_with::<Layer1>,
+        draw_layer_with::<Layer2>,
+        draw_layer_with::<Layer3>,
+        draw_layer_with::<Layer4>,
+        draw_layer_with::<Layer5>,
+        draw_layer_with::<Layer6>,
+        draw_layer_with::<Layer7>,
+        draw_layer_with::<Layer8>,
+        draw_layer_with::<Layer9>,
+    ];
+    for (layer, draw_fn) in draw_fns.into_iter().enumerate() {
+        if layers.contains(layer as u8) {
+            draw_fn(world, view);
+        }
+    }
 }
 
+/// draws a single layer through `State::coords`
 pub fn draw_layer<U: UserState<T>, T: TextureEnum, Layer: Component>(s: &mut State<U, T>, _dt: f32) {
-    for (_, (transform, drawable, tint)) in s.world.query_mut::<(&Transform, &Drawable, Option<&Tint>)>().with::<&Layer>().without::<&Hidden>() {
-        let pt = transform.d.transform_point2(Vec2::ZERO);
-        let dir_vec = transform.d.transform_vector2(Vec2::NEG_Y);
-        let dir_vec_magnitude = dir_vec.length() * s.coords.scale;
-        let pt: Vec2 = s.coords.to_screen(pt.x, pt.y).into();
-        
+    draw_layer_with::<Layer>(&s.world, s.coords.view_matrix());
+}
+
+/// the largest font size that text is rasterized at, larger text is scaled up
+const MAX_TEXT_RASTER_SIZE: f32 = 256.0;
+
+fn tint_color(color: Color, tint: Option<&Tint>) -> Color {
+    match tint {
+        Some(tint) => Color::new(color.r * tint.d.r, color.g * tint.d.g, color.b * tint.d.b, color.a * tint.d.a),
+        None => color,
+    }
+}
+
+fn tint_paint(paint: &Paint, tint: Option<&Tint>) -> Paint {
+    Paint {
+        fill: paint.fill.map(|c| tint_color(c, tint)),
+        stroke: paint.stroke.map(|c| tint_color(c, tint)),
+        stroke_width: paint.stroke_width,
+    }
+}
+
+/// fills a convex polygon of screen points as a triangle fan, then outlines it
+fn draw_polygon(points: &[Vec2], paint: &Paint, scale: f32) {
+    if points.len() < 2 {
+        return;
+    }
+    if let Some(fill) = paint.fill {
+        for i in 1..points.len().saturating_sub(1) {
+            draw_triangle(points[0], points[i], points[i + 1], fill);
+        }
+    }
+    if let Some(stroke) = paint.stroke {
+        let width = paint.stroke_width * scale;
+        for i in 0..points.len() {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            draw_line(a.x, a.y, b.x, b.y, width, stroke);
+        }
+    }
+}
+
+/// draws the source part of the texture, or the whole texture, at its pixel size scaled by the transform
+fn draw_sprite(d: Texture2D, source: Option<Rect>, dont_center: bool, pt: Vec2, dir_vec: Vec2, tint: Option<&Tint>) {
+    let size = source.map(|r| r.size()).unwrap_or_else(|| Vec2::new(d.width(), d.height())) * dir_vec.length();
+    let color = if let Some(tint) = tint {
+        tint.d
+    } else { WHITE };
+    let (x, y) = if dont_center {
+        (pt.x, pt.y)
+    } else {
+        (pt.x - size.x / 2.0, pt.y - size.y / 2.0)
+    };
+    draw_texture_ex(d, x, y, color, DrawTextureParams {
+        rotation: -dir_vec.angle_between(Vec2::NEG_Y),
+        dest_size: size.into(),
+        source,
+        ..Default::default()
+    });
+}
+
+/// draws a single layer with the given world to screen transform, eg: `CameraView::view_matrix`
+pub fn draw_layer_with<Layer: Component>(world: &World, view: Affine2) {
+    for (_, (transform, drawable, tint)) in world.query::<(&Transform, &Drawable, Option<&Tint>)>().with::<&Layer>().without::<&Hidden>().iter() {
+        // the entity's transform in screen space, including the camera's pan, scale and rotation
+        let screen_transform = view * transform.d;
+        let pt = screen_transform.transform_point2(Vec2::ZERO);
+        let dir_vec = screen_transform.transform_vector2(Vec2::NEG_Y);
+        let dir_vec_magnitude = dir_vec.length();
+
         match drawable {
             Drawable::Texture { d, dont_center } => {
-                let width = d.width() * dir_vec_magnitude;
-                let height = d.height() * dir_vec_magnitude;
-                let color = if let Some(tint) = tint {
-                    tint.d
-                } else { WHITE };
-                let (x, y) = if *dont_center {
-                    (pt.x, pt.y)
-                } else {
-                    (pt.x - width / 2.0, pt.y - height / 2.0)
+                draw_sprite(*d, None, *dont_center, pt, dir_vec, tint);
+            }
+            Drawable::Animation { d, dont_center } => {
+                if let Some(source) = d.source_rect() {
+                    draw_sprite(d.sheet, Some(source), *dont_center, pt, dir_vec, tint);
+                }
+            }
+            Drawable::Circle { radius, paint } => {
+                let paint = tint_paint(paint, tint);
+                let radius = radius * dir_vec_magnitude;
+                if let Some(fill) = paint.fill {
+                    draw_circle(pt.x, pt.y, radius, fill);
+                }
+                if let Some(stroke) = paint.stroke {
+                    draw_circle_lines(pt.x, pt.y, radius, paint.stroke_width * dir_vec_magnitude, stroke);
+                }
+            }
+            Drawable::Rect { size, paint } => {
+                let half = *size / 2.0;
+                let corners = [
+                    Vec2::new(-half.x, -half.y),
+                    Vec2::new(half.x, -half.y),
+                    Vec2::new(half.x, half.y),
+                    Vec2::new(-half.x, half.y),
+                ].map(|c| screen_transform.transform_point2(c));
+                draw_polygon(&corners, &tint_paint(paint, tint), dir_vec_magnitude);
+            }
+            Drawable::Polygon { points, paint } => {
+                let points: Vec<_> = points.iter().map(|p| screen_transform.transform_point2(*p)).collect();
+                draw_polygon(&points, &tint_paint(paint, tint), dir_vec_magnitude);
+            }
+            Drawable::Text { text, font, size, align, color, outline, outline_width, space } => {
+                let (pixel_scale, rotation) = match space {
+                    TextSpace::World => (dir_vec_magnitude, -dir_vec.angle_between(Vec2::NEG_Y)),
+                    TextSpace::Screen => (1.0, 0.0),
                 };
-                draw_texture_ex(*d, x, y, color, DrawTextureParams {
-                    rotation: -dir_vec.angle_between(Vec2::NEG_Y),
-                    dest_size: Vec2::new(width, height).into(),
+                // rasterized at the size on screen so that it stays sharp when zoomed in,
+                // up to a limit, beyond which the glyphs are scaled up instead
+                let screen_size = pixel_scale * size;
+                let font_size = screen_size.round().clamp(1.0, MAX_TEXT_RASTER_SIZE);
+                let params = TextParams {
+                    font: font.unwrap_or_default(),
+                    font_size: font_size as u16,
+                    font_scale: screen_size / font_size,
+                    color: tint_color(*color, tint),
+                    rotation,
                     ..Default::default()
-                });
+                };
+                let dimensions = measure_text(text, *font, params.font_size, params.font_scale);
+                let align_by = match align {
+                    TextAlign::Left => 0.0,
+                    TextAlign::Center => 0.5,
+                    TextAlign::Right => 1.0,
+                };
+                // from the anchor to the start of the baseline, rotated with the text
+                let offset = Vec2::new(-dimensions.width * align_by, dimensions.offset_y / 2.0);
+                let start = pt + Vec2::from_angle(rotation).rotate(offset);
+                if let Some(outline) = outline {
+                    let outline_params = TextParams { color: tint_color(*outline, tint), ..params };
+                    let width = outline_width * pixel_scale;
+                    for (dx, dy) in [(-1.0, -1.0), (0.0, -1.0), (1.0, -1.0), (-1.0, 0.0), (1.0, 0.0), (-1.0, 1.0), (0.0, 1.0), (1.0, 1.0)] {
+                        draw_text_ex(text, start.x + dx * width, start.y + dy * width, outline_params);
+                    }
+                }
+                draw_text_ex(text, start.x, start.y, params);
             }
+            Drawable::Polyline { points, color, width } => {
+                let color = tint_color(*color, tint);
+                let width = width * dir_vec_magnitude;
+                let points: Vec<_> = points.iter().map(|p| screen_transform.transform_point2(*p)).collect();
+                for pair in points.windows(2) {
+                    draw_line(pair[0].x, pair[0].y, pair[1].x, pair[1].y, width, color);
+                }
+            }
+        }
+    }
+    for (_, (transform, triangle, tint)) in world.query::<(&Transform, &Triangle, Option<&Tint>)>().with::<&Layer>().without::<&Hidden>().iter() {
+        let screen_transform = view * transform.d;
+        let points = [triangle.a, triangle.b, triangle.c].map(|p| screen_transform.transform_point2(p));
+        let scale = screen_transform.transform_vector2(Vec2::NEG_Y).length();
+        draw_polygon(&points, &tint_paint(&triangle.paint, tint), scale);
+    }
+    for (_, (transform, line, tint)) in world.query::<(&Transform, &LineSegment, Option<&Tint>)>().with::<&Layer>().without::<&Hidden>().iter() {
+        let screen_transform = view * transform.d;
+        let color = tint_color(line.color, tint);
+        let a = screen_transform.transform_point2(line.a);
+        let b = screen_transform.transform_point2(line.b);
+        let width = line.width * screen_transform.transform_vector2(Vec2::NEG_Y).length();
+        draw_line(a.x, a.y, b.x, b.y, width, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        system::{schedule::*, stateful::*, conditions::*},
+        testing::*,
+    };
+
+    /// runs the system like the schedule does, returning whether it ran
+    fn tick(system: &mut System<TestUserState, TestTextures>, s: &mut TestState) -> bool {
+        if !system.should_run(s, 0.1) {
+            return false;
+        }
+        system.run(s, 0.1);
+        true
+    }
+
+    fn pan_schedule() -> Schedule<TestUserState, TestTextures> {
+        Schedule::new().with_system(Stage::Render, sys!(handle_pan))
+    }
+
+    #[test]
+    fn handle_pan_zooms_around_the_cursor_headless() {
+        let mut s = TestState::headless();
+        s.coords = CoordTransform::default().with_zoom_smoothing(0.0);
+        s.input.screen_size = Vec2::new(800.0, 600.0);
+        s.input.set_mouse_position(200.0, 100.0);
+        s.input.scroll(0.0, 1.0);
+        let before = Vec2::from(s.coords.to_world(200.0, 100.0));
+        let s = run_headless(s, pan_schedule(), 1, 1.0 / 60.0);
+        assert!(s.coords.scale > 1.0);
+        assert!((Vec2::from(s.coords.to_world(200.0, 100.0)) - before).length() < 1e-3);
+        assert_eq!(s.coords.viewport_size, Vec2::new(800.0, 600.0));
+    }
+
+    #[test]
+    fn handle_pan_zooms_once_per_key_press_headless() {
+        let mut s = TestState::headless();
+        s.coords = CoordTransform::default().with_zoom_smoothing(0.0);
+        s.coords.zoom_scroll_enabled = false;
+        s.actions.bind(Action::ZoomIn, Binding::Key(KeyCode::Equal));
+        s.input.press_key(KeyCode::Equal);
+        s.input.scroll(0.0, 1.0);
+        let s = run_headless(s, pan_schedule(), 10, 1.0 / 60.0);
+        // a single step for the key, none for the disabled wheel
+        assert!((s.coords.scale - (1.0 + s.coords.zoom_speed)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn handle_pan_pans_with_held_keys_headless() {
+        let mut s = TestState::headless();
+        s.input.screen_size = Vec2::new(800.0, 600.0);
+        s.input.press_key(KeyCode::Right);
+        let s = run_headless(s, pan_schedule(), 2, 1.0 / 60.0);
+        assert_eq!(s.coords.pan_x, 2.0 * s.coords.wasd_pan_by);
+        assert!(s.coords.panned_by_user);
+    }
+
+    #[test]
+    fn handle_pan_clamps_to_bounds_headless() {
+        let mut s = TestState::headless();
+        s.coords = CoordTransform::default().with_bounds(Rect::new(0.0, 0.0, 1000.0, 1000.0));
+        s.input.screen_size = Vec2::new(100.0, 100.0);
+        s.input.press_key(KeyCode::Left);
+        let s = run_headless(s, pan_schedule(), 3, 1.0 / 60.0);
+        assert_eq!(s.coords.pan_x, 0.0);
+    }
+
+    #[test]
+    fn chained_conditions_short_circuit() {
+        let checked = Rc::new(Cell::new(0));
+        let checked_in = checked.clone();
+        let mut system = System::new(|_: &mut TestState, _| {}, "test")
+            .run_if(|_, _| false)
+            .run_if(move |_, _| {
+                checked_in.set(checked_in.get() + 1);
+                true
+            });
+        let mut s = TestState::empty();
+        assert!(!tick(&mut system, &mut s));
+        assert_eq!(checked.get(), 0);
+    }
+
+    #[test]
+    fn run_once_waits_for_the_other_conditions() {
+        let flag = Rc::new(Cell::new(false));
+        let flag_in = flag.clone();
+        let mut system = System::new(|_: &mut TestState, _| {}, "test")
+            .run_once()
+            .run_if(move |_, _| flag_in.get());
+        let mut s = TestState::empty();
+        assert!(!tick(&mut system, &mut s));
+        flag.set(true);
+        assert!(tick(&mut system, &mut s));
+        assert!(!tick(&mut system, &mut s));
+    }
+
+    #[test]
+    fn every_seconds_after_a_false_condition_keeps_its_ticks() {
+        let flag = Rc::new(Cell::new(false));
+        let flag_in = flag.clone();
+        let mut system = System::new(|_: &mut TestState, _| {}, "test")
+            .run_if(move |_, _| flag_in.get())
+            .run_if(every_seconds(0.2));
+        let mut s = TestState::empty();
+        for _ in 0..5 {
+            assert!(!tick(&mut system, &mut s));
         }
+        // the timer only advances while the flag holds
+        flag.set(true);
+        assert!(!tick(&mut system, &mut s));
+        assert!(tick(&mut system, &mut s));
     }
 }
@@ -0,0 +1,33 @@
+//! Minimal state types for tests, that can be created without a window.
+
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+
+use crate::data::{
+    world::*,
+    loading::*,
+};
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum TestTextures {
+    Sheet,
+}
+
+impl TextureEnum for TestTextures {
+    fn load() -> HashMap<Self, Texture2D> {
+        HashMap::new()
+    }
+    fn name(&self) -> &'static str {
+        "Sheet"
+    }
+    fn from_name(name: &str) -> Option<Self> {
+        (name == "Sheet").then_some(Self::Sheet)
+    }
+}
+
+#[derive(Default)]
+pub struct TestUserState;
+impl UserState<TestTextures> for TestUserState {}
+
+pub type TestState = State<TestUserState, TestTextures>;